@@ -63,7 +63,7 @@ pub unsafe extern "C" fn plugin_register() {
 }
 
 // Callback for dissection, called when a packet for this protocol is detected and dissected.
-unsafe fn dissect_callback(mut tree: DissectorSubTree) {
+unsafe fn dissect_callback(mut tree: DissectorSubTree) -> DissectResult {
     // Setting the info column
     tree.set_info_column("This is some info"); 
 
@@ -78,4 +78,7 @@ unsafe fn dissect_callback(mut tree: DissectorSubTree) {
     // Appending text to the field
     let mut test = tree.add_field("test.u8", IndexPosition::Current(0), 1, FieldEncoding::LittleEndian);
     test.append_text(" (Some Appended Text)");
+
+    // Report how many bytes of the packet belonged to this protocol
+    DissectResult::Consumed(tree.get_index() as u32)
 }
@@ -0,0 +1,170 @@
+use epan_sys::*;
+use std::{ffi::*, ptr::{null, null_mut}};
+
+use crate::wireshark_protocol::{get_global_plugin, write_truncated_cstring, WiresharkPlugin};
+
+// This callback is called to display an address of a custom address type, such as in the Source and Destination
+// columns. It is passed the raw bytes of the address.
+pub type AddressToStringCallback = unsafe fn(&[u8]) -> String;
+
+// This callback is called with the raw bytes of an address and returns the length of its string, without a null
+// terminator. Wireshark uses it to size the buffer passed to the to-string callback.
+pub type AddressStringLengthCallback = unsafe fn(&[u8]) -> usize;
+
+// Describes an address type for protocols whose endpoints are not IP or MAC addresses, such as station IDs or
+// channel names
+pub struct WiresharkAddressTypeArgs<'a> {
+    // The unique name of the address type, such as "AT_MYPROTO"
+    name: &'a str,
+
+    // The name of the address type shown to the user
+    pretty_name: &'a str,
+
+    // Function that formats an address
+    to_string_fn: AddressToStringCallback,
+
+    // Function that returns the length of a formatted address. If not set the address is formatted to find out.
+    string_length_fn: Option<AddressStringLengthCallback>,
+
+    // The fields holding the source and destination address, used to filter on the address from a column
+    filter_fields: Option<(&'a str, &'a str)>,
+}
+
+impl<'a> WiresharkAddressTypeArgs<'a> {
+    // Create a new address type definition
+    pub fn new(name: &'a str, pretty_name: &'a str, to_string_fn: AddressToStringCallback) -> Self {
+        Self {
+            name: name,
+            pretty_name: pretty_name,
+            to_string_fn: to_string_fn,
+            string_length_fn: None,
+            filter_fields: None,
+        }
+    }
+
+    // Set a function that returns the length of a formatted address without formatting it
+    pub fn with_string_length(mut self, string_length_fn: AddressStringLengthCallback) -> Self {
+        self.string_length_fn = Some(string_length_fn);
+        self
+    }
+
+    // Set the fields that hold the source and destination address, such as "myproto.src" and "myproto.dst", so
+    // filters can be applied from the Source and Destination columns
+    pub fn with_filter_fields(mut self, source: &'a str, destination: &'a str) -> Self {
+        self.filter_fields = Some((source, destination));
+        self
+    }
+}
+
+// An address type registered with Wireshark
+pub(crate) struct WiresharkAddressType {
+    pub(crate) name: String,
+    pretty_name: String,
+    to_string_fn: AddressToStringCallback,
+    string_length_fn: Option<AddressStringLengthCallback>,
+    filter_fields: Option<(*const c_char, *const c_char)>,
+
+    // The type Wireshark assigned to the address type, or -1 if it has not yet been registered
+    pub(crate) type_id: c_int,
+}
+
+impl WiresharkAddressType {
+    pub(crate) fn new(plugin: &WiresharkPlugin, args: WiresharkAddressTypeArgs<'_>) -> Self {
+        Self {
+            name: args.name.to_string(),
+            pretty_name: args.pretty_name.to_string(),
+            to_string_fn: args.to_string_fn,
+            string_length_fn: args.string_length_fn,
+            filter_fields: args.filter_fields.map(|(source, destination)| {
+                (
+                    plugin.alloc_string(source) as *const c_char,
+                    plugin.alloc_string(destination) as *const c_char,
+                )
+            }),
+            type_id: -1,
+        }
+    }
+
+    // Register the address type with Wireshark
+    pub(crate) unsafe fn register(&mut self, plugin: &WiresharkPlugin) {
+        self.type_id = address_type_dissector_register(
+            plugin.alloc_string(&self.name),
+            plugin.alloc_string(&self.pretty_name),
+            Some(address_to_str_dispatcher),
+            Some(address_str_len_dispatcher),
+            None,
+            if self.filter_fields.is_some() {
+                Some(address_col_filter_dispatcher)
+            } else {
+                None
+            },
+            None,
+            None,
+            None,
+        );
+    }
+}
+
+// Set a packet's address to a copy of the given bytes, which lives until the packet is done
+pub(crate) unsafe fn set_packet_address(pinfo: *mut _packet_info, address: *mut address, type_id: c_int, data: &[u8]) {
+    let data_ptr = if data.is_empty() {
+        null()
+    } else {
+        wmem_memdup((*pinfo).pool, data.as_ptr() as *const c_void, data.len()) as *const c_void
+    };
+
+    (*address).type_ = type_id;
+    (*address).len = data.len() as c_int;
+    (*address).data = data_ptr;
+    (*address).priv_ = null_mut();
+}
+
+// Get the raw bytes of an address
+unsafe fn address_bytes<'a>(address: *const address) -> &'a [u8] {
+    if (*address).data.is_null() || (*address).len <= 0 {
+        return &[];
+    }
+
+    std::slice::from_raw_parts((*address).data as *const u8, (*address).len as usize)
+}
+
+unsafe extern "C" fn address_to_str_dispatcher(address: *const address, buf: *mut c_char, buf_len: c_int) -> c_int {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let address_type = plugref.get_address_type_by_id((*address).type_);
+
+    let text = (address_type.to_string_fn)(address_bytes(address));
+    if buf_len <= 0 {
+        return 0;
+    }
+
+    let length = write_truncated_cstring(&text, buf, buf_len as usize);
+
+    // Wireshark expects the length including the null terminator
+    length as c_int + 1
+}
+
+unsafe extern "C" fn address_str_len_dispatcher(address: *const address) -> c_int {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let address_type = plugref.get_address_type_by_id((*address).type_);
+
+    let length = match address_type.string_length_fn {
+        Some(string_length_fn) => string_length_fn(address_bytes(address)),
+        None => (address_type.to_string_fn)(address_bytes(address)).len(),
+    };
+
+    length as c_int + 1
+}
+
+unsafe extern "C" fn address_col_filter_dispatcher(address: *const address, is_src: bool) -> *const c_char {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let address_type = plugref.get_address_type_by_id((*address).type_);
+
+    match address_type.filter_fields {
+        Some((source, _)) if is_src => source,
+        Some((_, destination)) => destination,
+        None => null(),
+    }
+}
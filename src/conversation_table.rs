@@ -0,0 +1,308 @@
+use epan_sys::*;
+use std::{any::Any, ffi::*, mem::zeroed, net::IpAddr};
+
+use crate::tap::{tap_record_tap_id, tap_record_value};
+use crate::wireshark_protocol::{get_global_plugin, WiresharkPlugin};
+
+// An address of one side of a conversation
+pub enum EndpointAddress {
+    // The source address of the packet, such as one set with DissectorSubTree::set_source_address
+    PacketSource,
+    // The destination address of the packet, such as one set with DissectorSubTree::set_destination_address
+    PacketDestination,
+    // An IPv4 or IPv6 address
+    Ip(IpAddr),
+    // An address of a type added with WiresharkPlugin::add_address_type
+    Custom { address_type: String, data: Vec<u8> },
+}
+
+// The two sides of the conversation a packet belongs to, each with an address and a port-like sub-identifier
+// such as a channel or unit number
+pub struct ConversationEndpoints {
+    pub source: EndpointAddress,
+    pub source_port: u32,
+    pub destination: EndpointAddress,
+    pub destination_port: u32,
+}
+
+// This callback is called for each record of the given type that is queued to the protocol's tap while the
+// Conversations or Endpoints dialog is open. It returns the endpoints of the packet, or None if the packet is not
+// part of a conversation.
+pub type ConversationCallback<T> = unsafe fn(&T) -> Option<ConversationEndpoints>;
+
+// Describes how a protocol is shown in the Conversations and Endpoints dialogs
+pub struct WiresharkConversationTableArgs<'a> {
+    // True if the endpoints have no port-like sub-identifier, which hides the port columns
+    hide_ports: bool,
+
+    // The fields used to filter on a source, destination or either address
+    address_filters: Option<(&'a str, &'a str, &'a str)>,
+
+    // The fields used to filter on a source, destination or either port
+    port_filters: Option<(&'a str, &'a str, &'a str)>,
+}
+
+impl<'a> WiresharkConversationTableArgs<'a> {
+    // Create a new conversation table definition
+    pub fn new() -> Self {
+        Self {
+            hide_ports: false,
+            address_filters: None,
+            port_filters: None,
+        }
+    }
+
+    // Hide the port columns, for protocols whose endpoints are only an address
+    pub fn with_hide_ports(mut self) -> Self {
+        self.hide_ports = true;
+        self
+    }
+
+    // Set the fields used to build a filter from the selected conversation or endpoint, such as "myproto.src",
+    // "myproto.dst" and "myproto.addr"
+    pub fn with_address_filters(mut self, source: &'a str, destination: &'a str, any: &'a str) -> Self {
+        self.address_filters = Some((source, destination, any));
+        self
+    }
+
+    // Set the fields used to build a filter from the ports of the selected conversation or endpoint, such as
+    // "myproto.srcport", "myproto.dstport" and "myproto.port"
+    pub fn with_port_filters(mut self, source: &'a str, destination: &'a str, any: &'a str) -> Self {
+        self.port_filters = Some((source, destination, any));
+        self
+    }
+}
+
+impl<'a> Default for WiresharkConversationTableArgs<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The filter fields of a conversation table, indexed by conv_filter_type_e
+#[derive(Clone, Copy)]
+struct ConversationFilters {
+    filters: [*const c_char; 6],
+}
+
+impl ConversationFilters {
+    unsafe fn get(&self, filter_type: conv_filter_type_e) -> *const c_char {
+        match self.filters.get(filter_type as usize) {
+            Some(filter) => *filter,
+            None => CONV_FILTER_INVALID.as_ptr() as *const c_char,
+        }
+    }
+}
+
+// Wireshark passes the dissector info back to its filter callback, so the filters are stored right after it
+#[repr(C)]
+struct ConversationDissectorInfo {
+    info: ct_dissector_info_t,
+    filters: ConversationFilters,
+}
+
+#[repr(C)]
+struct EndpointDissectorInfo {
+    info: et_dissector_info_t,
+    filters: ConversationFilters,
+}
+
+// A conversation table with its record type erased
+pub(crate) struct WiresharkConversationTable {
+    hide_ports: bool,
+    address_filters: Option<[String; 3]>,
+    port_filters: Option<[String; 3]>,
+    endpoints_fn: Box<dyn Fn(&dyn Any) -> Option<ConversationEndpoints>>,
+    ct_info: *mut ConversationDissectorInfo,
+    et_info: *mut EndpointDissectorInfo,
+}
+
+impl WiresharkConversationTable {
+    pub(crate) fn new<T: 'static>(
+        args: WiresharkConversationTableArgs<'_>,
+        endpoints_fn: ConversationCallback<T>,
+    ) -> Self {
+        let to_owned = |(src, dst, any): (&str, &str, &str)| [src.to_string(), dst.to_string(), any.to_string()];
+
+        Self {
+            hide_ports: args.hide_ports,
+            address_filters: args.address_filters.map(to_owned),
+            port_filters: args.port_filters.map(to_owned),
+            endpoints_fn: Box::new(move |record| match record.downcast_ref::<T>() {
+                Some(record) => unsafe { endpoints_fn(record) },
+                None => None,
+            }),
+            ct_info: std::ptr::null_mut(),
+            et_info: std::ptr::null_mut(),
+        }
+    }
+
+    // Register the conversation and endpoint tables of the protocol
+    pub(crate) unsafe fn register(&mut self, plugin: &WiresharkPlugin, proto_handle: c_int) {
+        let invalid = CONV_FILTER_INVALID.as_ptr() as *const c_char;
+        let alloc_filters = |filters: &Option<[String; 3]>| match filters {
+            Some(filters) => filters.each_ref().map(|filter| plugin.alloc_string(filter) as *const c_char),
+            None => [invalid; 3],
+        };
+        let [src_address, dst_address, any_address] = alloc_filters(&self.address_filters);
+        let [src_port, dst_port, any_port] = alloc_filters(&self.port_filters);
+
+        // Ordered like conv_filter_type_e
+        let filters = ConversationFilters {
+            filters: [src_address, dst_address, any_address, src_port, dst_port, any_port],
+        };
+
+        self.ct_info = plugin.alloc_value(ConversationDissectorInfo {
+            info: ct_dissector_info_t {
+                get_filter_type: Some(conversation_filter_dispatcher),
+            },
+            filters: filters,
+        });
+        self.et_info = plugin.alloc_value(EndpointDissectorInfo {
+            info: et_dissector_info_t {
+                get_filter_type: Some(endpoint_filter_dispatcher),
+            },
+            filters: filters,
+        });
+
+        register_conversation_table(
+            proto_handle,
+            self.hide_ports,
+            Some(conversation_packet_dispatcher),
+            Some(endpoint_packet_dispatcher),
+        );
+    }
+}
+
+// Convert an endpoint address to a Wireshark address. The returned bytes hold the data of the address and must
+// be kept until the address is no longer used.
+unsafe fn resolve_endpoint_address(
+    plugin: &WiresharkPlugin,
+    pinfo: *mut _packet_info,
+    endpoint: &EndpointAddress,
+) -> (address, Vec<u8>) {
+    let (type_id, data) = match endpoint {
+        EndpointAddress::PacketSource => return ((*pinfo).src, Vec::new()),
+        EndpointAddress::PacketDestination => return ((*pinfo).dst, Vec::new()),
+        EndpointAddress::Ip(IpAddr::V4(ip)) => (address_type_AT_IPv4 as c_int, ip.octets().to_vec()),
+        EndpointAddress::Ip(IpAddr::V6(ip)) => (address_type_AT_IPv6 as c_int, ip.octets().to_vec()),
+        EndpointAddress::Custom { address_type, data } => (plugin.get_address_type(address_type).type_id, data.clone()),
+    };
+
+    let mut addr: address = zeroed();
+    addr.type_ = type_id;
+    addr.len = data.len() as c_int;
+    addr.data = data.as_ptr() as *const c_void;
+
+    (addr, data)
+}
+
+// Get the conversation table of the protocol that queued a tap record, and the endpoints of the packet
+unsafe fn record_endpoints<'a>(
+    plugin: &'a WiresharkPlugin,
+    data: *const c_void,
+) -> Option<(&'a WiresharkConversationTable, ConversationEndpoints)> {
+    let table = plugin
+        .get_protocol_by_tap_id(tap_record_tap_id(data)?)?
+        .conversation_table
+        .as_ref()?;
+
+    let endpoints = (table.endpoints_fn)(tap_record_value(data)?)?;
+    Some((table, endpoints))
+}
+
+unsafe extern "C" fn conversation_packet_dispatcher(
+    tapdata: *mut c_void,
+    pinfo: *mut _packet_info,
+    _edt: *mut epan_dissect_t,
+    data: *const c_void,
+    _flags: tap_flags_t,
+) -> tap_packet_status {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+
+    let (table, endpoints) = match record_endpoints(&plugref, data) {
+        Some(result) => result,
+        None => return tap_packet_status_TAP_PACKET_DONT_REDRAW,
+    };
+
+    let (src, _src_data) = resolve_endpoint_address(&plugref, pinfo, &endpoints.source);
+    let (dst, _dst_data) = resolve_endpoint_address(&plugref, pinfo, &endpoints.destination);
+
+    add_conversation_table_data(
+        tapdata as *mut conv_hash_t,
+        &src,
+        &dst,
+        endpoints.source_port,
+        endpoints.destination_port,
+        1,
+        (*(*pinfo).fd).pkt_len as c_int,
+        &mut (*pinfo).rel_ts,
+        &mut (*pinfo).abs_ts,
+        table.ct_info as *mut ct_dissector_info_t,
+        conversation_type_CONVERSATION_NONE,
+    );
+
+    tap_packet_status_TAP_PACKET_REDRAW
+}
+
+unsafe extern "C" fn endpoint_packet_dispatcher(
+    tapdata: *mut c_void,
+    pinfo: *mut _packet_info,
+    _edt: *mut epan_dissect_t,
+    data: *const c_void,
+    _flags: tap_flags_t,
+) -> tap_packet_status {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+
+    let (table, endpoints) = match record_endpoints(&plugref, data) {
+        Some(result) => result,
+        None => return tap_packet_status_TAP_PACKET_DONT_REDRAW,
+    };
+
+    let (src, _src_data) = resolve_endpoint_address(&plugref, pinfo, &endpoints.source);
+    let (dst, _dst_data) = resolve_endpoint_address(&plugref, pinfo, &endpoints.destination);
+    let num_bytes = (*(*pinfo).fd).pkt_len as c_int;
+
+    // Count the packet for both the sender and the receiver
+    let et_info = table.et_info as *mut et_dissector_info_t;
+    add_endpoint_table_data(
+        tapdata as *mut conv_hash_t,
+        &src,
+        endpoints.source_port,
+        true,
+        1,
+        num_bytes,
+        et_info,
+        endpoint_type_ENDPOINT_NONE,
+    );
+    add_endpoint_table_data(
+        tapdata as *mut conv_hash_t,
+        &dst,
+        endpoints.destination_port,
+        false,
+        1,
+        num_bytes,
+        et_info,
+        endpoint_type_ENDPOINT_NONE,
+    );
+
+    tap_packet_status_TAP_PACKET_REDRAW
+}
+
+unsafe extern "C" fn conversation_filter_dispatcher(
+    conv_item: *mut conv_item_t,
+    filter_type: conv_filter_type_e,
+) -> *const c_char {
+    let info = (*conv_item).dissector_info as *const ConversationDissectorInfo;
+    (*info).filters.get(filter_type)
+}
+
+unsafe extern "C" fn endpoint_filter_dispatcher(
+    endpoint: *mut endpoint_item_t,
+    filter_type: conv_filter_type_e,
+) -> *const c_char {
+    let info = (*endpoint).dissector_info as *const EndpointDissectorInfo;
+    (*info).filters.get(filter_type)
+}
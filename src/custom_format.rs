@@ -0,0 +1,105 @@
+use epan_sys::*;
+use std::ffi::*;
+
+use crate::defines::FieldType;
+use crate::slots::{claim_slot, slot_dispatchers, MAX_SLOTS};
+use crate::wireshark_protocol::{get_global_plugin, write_truncated_cstring, WiresharkPlugin};
+
+// A Rust function used to format the value of a field displayed with FieldDisplayType::BaseCustom
+pub(crate) enum WiresharkCustomFormat {
+    // Formats an integer field. Signed values are passed as their two's complement bit pattern.
+    Integer(Box<dyn Fn(u64) -> String>),
+    // Formats a float or double field
+    Float(Box<dyn Fn(f64) -> String>),
+}
+
+// Wireshark does not pass any context to custom formatting functions, so each field needs its own function
+static CUSTOM_FORMAT_DISPATCHERS: [custom_fmt_func_t; MAX_SLOTS] = slot_dispatchers!(custom_format_dispatcher);
+
+static CUSTOM_FORMAT_64_DISPATCHERS: [custom_fmt_func_64_t; MAX_SLOTS] = slot_dispatchers!(custom_format_64_dispatcher);
+
+static CUSTOM_FORMAT_DOUBLE_DISPATCHERS: [custom_fmt_func_double_t; MAX_SLOTS] =
+    slot_dispatchers!(custom_format_double_dispatcher);
+
+// Keep the custom format in the plugin and return the function Wireshark should call to format the field,
+// which is stored in the strings of the field
+pub(crate) fn register_custom_format(
+    plugin: &mut WiresharkPlugin,
+    field_id: &str,
+    field_type: FieldType,
+    format: WiresharkCustomFormat,
+) -> *const c_void {
+    let slot = claim_slot(
+        plugin.custom_formats.len(),
+        &format!("Field {}", field_id),
+        "fields with custom formatting",
+    );
+
+    // Wireshark calls a different kind of function depending on the size of the value. Characters and frame
+    // numbers are not accepted, as Wireshark does not allow them to be displayed with a custom format.
+    let dispatcher = match (&format, field_type) {
+        (
+            WiresharkCustomFormat::Integer(_),
+            FieldType::Uint8
+            | FieldType::Uint16
+            | FieldType::Uint24
+            | FieldType::Uint32
+            | FieldType::Int8
+            | FieldType::Int16
+            | FieldType::Int24
+            | FieldType::Int32,
+        ) => CUSTOM_FORMAT_DISPATCHERS[slot].unwrap() as *const c_void,
+        (
+            WiresharkCustomFormat::Integer(_),
+            FieldType::Uint40
+            | FieldType::Uint48
+            | FieldType::Uint56
+            | FieldType::Uint64
+            | FieldType::Int40
+            | FieldType::Int48
+            | FieldType::Int56
+            | FieldType::Int64,
+        ) => CUSTOM_FORMAT_64_DISPATCHERS[slot].unwrap() as *const c_void,
+        (WiresharkCustomFormat::Float(_), FieldType::Float | FieldType::Double) => {
+            CUSTOM_FORMAT_DOUBLE_DISPATCHERS[slot].unwrap() as *const c_void
+        }
+        _ => panic!(
+            "Custom format of field {} does not match its field type {:?}.",
+            field_id, field_type
+        ),
+    };
+
+    plugin.custom_formats.push(format);
+    dispatcher
+}
+
+unsafe extern "C" fn custom_format_dispatcher<const SLOT: usize>(label: *mut c_char, value: u32) {
+    write_custom_label(SLOT, label, |format| match format {
+        WiresharkCustomFormat::Integer(format_fn) => format_fn(value as u64),
+        WiresharkCustomFormat::Float(_) => unreachable!(),
+    });
+}
+
+unsafe extern "C" fn custom_format_64_dispatcher<const SLOT: usize>(label: *mut c_char, value: u64) {
+    write_custom_label(SLOT, label, |format| match format {
+        WiresharkCustomFormat::Integer(format_fn) => format_fn(value),
+        WiresharkCustomFormat::Float(_) => unreachable!(),
+    });
+}
+
+unsafe extern "C" fn custom_format_double_dispatcher<const SLOT: usize>(label: *mut c_char, value: f64) {
+    write_custom_label(SLOT, label, |format| match format {
+        WiresharkCustomFormat::Float(format_fn) => format_fn(value),
+        WiresharkCustomFormat::Integer(_) => unreachable!(),
+    });
+}
+
+// Format the value with the custom format in the given slot and copy the text into Wireshark's label buffer,
+// which holds ITEM_LABEL_LENGTH bytes including the null terminator
+unsafe fn write_custom_label(slot: usize, label: *mut c_char, format_fn: impl Fn(&WiresharkCustomFormat) -> String) {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let text = format_fn(&plugref.custom_formats[slot]);
+
+    write_truncated_cstring(&text, label, ITEM_LABEL_LENGTH as usize);
+}
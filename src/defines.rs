@@ -79,6 +79,46 @@ impl FieldDisplayType {
     }
 }
 
+// The width of the whole value that a boolean field with a bitmask is a flag of
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldBitWidth {
+    Bits8 = 8,
+    Bits16 = 16,
+    Bits24 = 24,
+    Bits32 = 32,
+    Bits40 = 40,
+    Bits48 = 48,
+    Bits56 = 56,
+    Bits64 = 64,
+}
+
+impl FieldBitWidth {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            8 => Some(Self::Bits8),
+            16 => Some(Self::Bits16),
+            24 => Some(Self::Bits24),
+            32 => Some(Self::Bits32),
+            40 => Some(Self::Bits40),
+            48 => Some(Self::Bits48),
+            56 => Some(Self::Bits56),
+            64 => Some(Self::Bits64),
+            _ => None,
+        }
+    }
+
+    // The smallest width that holds every bit of the given mask
+    pub fn from_bitmask(bitmask: u64) -> Self {
+        let bits = 64 - bitmask.leading_zeros() as i32;
+        Self::from_i32(((bits + 7) / 8).max(1) * 8).unwrap()
+    }
+
+    pub fn to_i32(self) -> i32 {
+        self as i32
+    }
+}
+
 
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -325,4 +365,171 @@ impl StringFieldEncoding {
             _ => None,
         }
     }
-}
\ No newline at end of file
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpertGroup {
+    Checksum = 0x01000000,      // PI_CHECKSUM: The protocol field has a bad checksum
+    Sequence = 0x02000000,      // PI_SEQUENCE: The protocol field indicates a sequence problem
+    ResponseCode = 0x03000000,  // PI_RESPONSE_CODE: The protocol field indicates a bad application response code
+    RequestCode = 0x04000000,   // PI_REQUEST_CODE: The protocol field indicates an application request
+    Undecoded = 0x05000000,     // PI_UNDECODED: The data is undecoded, the protocol dissection is incomplete here
+    Reassemble = 0x06000000,    // PI_REASSEMBLE: The protocol field indicates a reassemble
+    Malformed = 0x07000000,     // PI_MALFORMED: The packet data is malformed
+    Debug = 0x08000000,         // PI_DEBUG: A generic debugging message
+    Protocol = 0x09000000,      // PI_PROTOCOL: The protocol field violates a protocol specification
+    Security = 0x0a000000,      // PI_SECURITY: The protocol field indicates a security problem
+    Comments = 0x0b000000,      // PI_COMMENTS_GROUP: The protocol field indicates a packet comment
+    Decryption = 0x0c000000,    // PI_DECRYPTION: The protocol field indicates a decryption problem
+    Assumption = 0x0d000000,    // PI_ASSUMPTION: The protocol field has incomplete data, decode based on assumed value
+    Deprecated = 0x0e000000,    // PI_DEPRECATED: The protocol field has been deprecated
+    Receive = 0x0f000000,       // PI_RECEIVE: Something happened as part of the receive process
+    Interface = 0x10000000,     // PI_INTERFACE: Something happened with the interface
+    DissectorBug = 0x11000000,  // PI_DISSECTOR_BUG: A bug in a dissector was detected
+}
+
+impl ExpertGroup {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0x01000000 => Some(Self::Checksum),
+            0x02000000 => Some(Self::Sequence),
+            0x03000000 => Some(Self::ResponseCode),
+            0x04000000 => Some(Self::RequestCode),
+            0x05000000 => Some(Self::Undecoded),
+            0x06000000 => Some(Self::Reassemble),
+            0x07000000 => Some(Self::Malformed),
+            0x08000000 => Some(Self::Debug),
+            0x09000000 => Some(Self::Protocol),
+            0x0a000000 => Some(Self::Security),
+            0x0b000000 => Some(Self::Comments),
+            0x0c000000 => Some(Self::Decryption),
+            0x0d000000 => Some(Self::Assumption),
+            0x0e000000 => Some(Self::Deprecated),
+            0x0f000000 => Some(Self::Receive),
+            0x10000000 => Some(Self::Interface),
+            0x11000000 => Some(Self::DissectorBug),
+            _ => None,
+        }
+    }
+
+    pub fn to_i32(self) -> i32 {
+        self as i32
+    }
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpertSeverity {
+    Comment = 0x00100000,  // PI_COMMENT: Packet comment
+    Chat = 0x00200000,     // PI_CHAT: Usual workflow, e.g. TCP connection establishing
+    Note = 0x00400000,     // PI_NOTE: Notable messages, e.g. an application returned an "unusual" error code
+    Warn = 0x00600000,     // PI_WARN: Warning, e.g. application returned an "unusual" error code
+    Error = 0x00800000,    // PI_ERROR: Serious problems, e.g. a malformed packet
+}
+
+impl ExpertSeverity {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0x00100000 => Some(Self::Comment),
+            0x00200000 => Some(Self::Chat),
+            0x00400000 => Some(Self::Note),
+            0x00600000 => Some(Self::Warn),
+            0x00800000 => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    pub fn to_i32(self) -> i32 {
+        self as i32
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportType {
+    None = 0,       // PT_NONE: no port number
+    Sctp = 1,       // PT_SCTP: SCTP
+    Tcp = 2,        // PT_TCP: TCP
+    Udp = 3,        // PT_UDP: UDP
+    Dccp = 4,       // PT_DCCP: DCCP
+    Ipx = 5,        // PT_IPX: IPX sockets
+    Ddp = 6,        // PT_DDP: DDP AppleTalk connection
+    Idp = 7,        // PT_IDP: XNS IDP sockets
+    Usb = 8,        // PT_USB: USB endpoint 0xffff means the host
+    I2c = 9,        // PT_I2C
+    Ibqp = 10,      // PT_IBQP: Infiniband QP number
+    Bluetooth = 11, // PT_BLUETOOTH
+    IwarpMpa = 12,  // PT_IWARP_MPA: iWarp MPA
+    Mctp = 13,      // PT_MCTP
+}
+
+impl TransportType {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::None),
+            1 => Some(Self::Sctp),
+            2 => Some(Self::Tcp),
+            3 => Some(Self::Udp),
+            4 => Some(Self::Dccp),
+            5 => Some(Self::Ipx),
+            6 => Some(Self::Ddp),
+            7 => Some(Self::Idp),
+            8 => Some(Self::Usb),
+            9 => Some(Self::I2c),
+            10 => Some(Self::Ibqp),
+            11 => Some(Self::Bluetooth),
+            12 => Some(Self::IwarpMpa),
+            13 => Some(Self::Mctp),
+            _ => None,
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        self as u32
+    }
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerDirection {
+    Unknown = -1, // P2P_DIR_UNKNOWN: the direction of a point-to-point link is not known
+    Sent = 0,     // P2P_DIR_SENT: sent by this side of the link
+    Received = 1, // P2P_DIR_RECV: received by this side of the link
+}
+
+impl PeerDirection {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            -1 => Some(Self::Unknown),
+            0 => Some(Self::Sent),
+            1 => Some(Self::Received),
+            _ => None,
+        }
+    }
+
+    pub fn to_i32(self) -> i32 {
+        self as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_bit_width_from_bitmask() {
+        assert_eq!(FieldBitWidth::from_bitmask(0), FieldBitWidth::Bits8);
+        assert_eq!(FieldBitWidth::from_bitmask(0x01), FieldBitWidth::Bits8);
+        assert_eq!(FieldBitWidth::from_bitmask(0x80), FieldBitWidth::Bits8);
+        assert_eq!(FieldBitWidth::from_bitmask(0x0100), FieldBitWidth::Bits16);
+        assert_eq!(FieldBitWidth::from_bitmask(0x8000), FieldBitWidth::Bits16);
+        assert_eq!(FieldBitWidth::from_bitmask(0x0001_0000), FieldBitWidth::Bits24);
+        assert_eq!(FieldBitWidth::from_bitmask(0x8000_0000), FieldBitWidth::Bits32);
+        assert_eq!(FieldBitWidth::from_bitmask(0x0000_0001_0000_0000), FieldBitWidth::Bits40);
+        assert_eq!(FieldBitWidth::from_bitmask(0x0000_8000_0000_0000), FieldBitWidth::Bits48);
+        assert_eq!(FieldBitWidth::from_bitmask(0x0080_0000_0000_0000), FieldBitWidth::Bits56);
+        assert_eq!(FieldBitWidth::from_bitmask(0x8000_0000_0000_0000), FieldBitWidth::Bits64);
+        assert_eq!(FieldBitWidth::from_bitmask(u64::MAX), FieldBitWidth::Bits64);
+    }
+}
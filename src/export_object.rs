@@ -0,0 +1,55 @@
+use epan_sys::*;
+use std::{ffi::*, ptr::null_mut};
+
+use crate::tap::tap_record_value;
+
+// An object published with DissectorSubTree::export_object, queued to the protocol's tap as a record so the
+// Export Objects dialog and tshark --export-objects can save it
+pub(crate) struct ExportedObject {
+    pub(crate) filename: String,
+    pub(crate) content_type: String,
+    pub(crate) payload: Vec<u8>,
+}
+
+// Register the protocol with the Export Objects dialog
+pub(crate) unsafe fn register_export_objects(proto_handle: c_int) {
+    register_export_object(proto_handle, Some(export_object_packet_dispatcher), None);
+}
+
+// Copy a string into memory owned by the export object list, which frees it with g_free
+unsafe fn export_object_strdup(s: &str) -> *mut c_char {
+    let s = CString::new(s.replace('\0', "")).unwrap();
+    g_strdup(s.as_ptr())
+}
+
+// Called by Wireshark for each record queued to the tap of a protocol while its objects are being exported
+unsafe extern "C" fn export_object_packet_dispatcher(
+    tapdata: *mut c_void,
+    pinfo: *mut _packet_info,
+    _edt: *mut epan_dissect_t,
+    data: *const c_void,
+    _flags: tap_flags_t,
+) -> tap_packet_status {
+    let object = match tap_record_value(data).and_then(|value| value.downcast_ref::<ExportedObject>()) {
+        Some(object) => object,
+        None => return tap_packet_status_TAP_PACKET_DONT_REDRAW,
+    };
+    let object_list = tapdata as *mut export_object_list_t;
+
+    // The entry and everything it points to is freed by the export object list
+    let hostname = address_to_str(null_mut(), &(*pinfo).src);
+    let entry: *mut export_object_entry_t = g_malloc0(std::mem::size_of::<export_object_entry_t>()) as *mut _;
+    (*entry).pkt_num = (*pinfo).num;
+    (*entry).hostname = g_strdup(hostname);
+    (*entry).content_type = export_object_strdup(&object.content_type);
+    (*entry).filename = export_object_strdup(&object.filename);
+    (*entry).payload_len = object.payload.len();
+    (*entry).payload_data = g_memdup2(object.payload.as_ptr() as *const c_void, object.payload.len()) as *mut u8;
+    wmem_free(null_mut(), hostname as *mut c_void);
+
+    if let Some(add_entry) = (*object_list).add_entry {
+        add_entry((*object_list).gui_data, entry);
+    }
+
+    tap_packet_status_TAP_PACKET_REDRAW
+}
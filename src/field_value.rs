@@ -0,0 +1,235 @@
+use epan_sys::*;
+use std::{
+    ffi::*,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::defines::FieldType;
+
+// The value of a field of any protocol, read from the packet tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    // An unsigned integer, character or frame number field
+    Unsigned(u64),
+    // A signed integer field
+    Signed(i64),
+    // A boolean field
+    Boolean(bool),
+    // A float or double field
+    Double(f64),
+    // A string field
+    String(String),
+    // A byte array field, such as FieldType::Bytes or FieldType::Ether
+    Bytes(Vec<u8>),
+    // An IPv4 or IPv6 address field
+    Address(IpAddr),
+    // An absolute time field, such as frame.time_epoch
+    AbsoluteTime(SystemTime),
+    // A relative time field, such as frame.time_delta. Negative times are clamped to zero.
+    RelativeTime(Duration),
+    // Any other type of field, as the text Wireshark displays for it
+    Display(String),
+}
+
+impl FieldValue {
+    // Get the value of an unsigned integer field
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            FieldValue::Unsigned(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Get the value of a signed integer field
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            FieldValue::Signed(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Get the value of a string field
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FieldValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Get the value of a byte array field
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            FieldValue::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Get the value of an IPv4 or IPv6 address field
+    pub fn as_address(&self) -> Option<IpAddr> {
+        match self {
+            FieldValue::Address(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Get the value of an absolute time field
+    pub fn as_time(&self) -> Option<SystemTime> {
+        match self {
+            FieldValue::AbsoluteTime(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Get the value of a relative time field
+    pub fn as_duration(&self) -> Option<Duration> {
+        match self {
+            FieldValue::RelativeTime(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Read the value of a field in the packet tree
+    unsafe fn from_field_info(finfo: *mut field_info) -> Self {
+        let fv = (*finfo).value;
+        let field_type = FieldType::from_u32((*(*finfo).hfinfo).type_ as u32);
+
+        match field_type {
+            Some(
+                FieldType::Char
+                | FieldType::Uint8
+                | FieldType::Uint16
+                | FieldType::Uint24
+                | FieldType::Uint32
+                | FieldType::Framenum,
+            ) => FieldValue::Unsigned(fvalue_get_uinteger(fv) as u64),
+            Some(FieldType::Uint40 | FieldType::Uint48 | FieldType::Uint56 | FieldType::Uint64 | FieldType::Eui64) => {
+                FieldValue::Unsigned(fvalue_get_uinteger64(fv))
+            }
+            Some(FieldType::Int8 | FieldType::Int16 | FieldType::Int24 | FieldType::Int32) => {
+                FieldValue::Signed(fvalue_get_sinteger(fv) as i64)
+            }
+            Some(FieldType::Int40 | FieldType::Int48 | FieldType::Int56 | FieldType::Int64) => {
+                FieldValue::Signed(fvalue_get_sinteger64(fv))
+            }
+            Some(FieldType::Boolean) => FieldValue::Boolean(fvalue_get_uinteger64(fv) != 0),
+            Some(FieldType::Float | FieldType::Double) => FieldValue::Double(fvalue_get_floating(fv)),
+            Some(
+                FieldType::String
+                | FieldType::Stringz
+                | FieldType::UintString
+                | FieldType::StringzPad
+                | FieldType::StringzTrunc,
+            ) => {
+                let value = fvalue_get_string(fv);
+                if value.is_null() {
+                    return FieldValue::String(String::new());
+                }
+
+                FieldValue::String(CStr::from_ptr(value).to_string_lossy().into_owned())
+            }
+            Some(
+                FieldType::Bytes
+                | FieldType::UintBytes
+                | FieldType::Ether
+                | FieldType::Oid
+                | FieldType::RelOid
+                | FieldType::SystemId,
+            ) => {
+                let data = fvalue_get_bytes_data(fv);
+                if data.is_null() {
+                    return FieldValue::Bytes(Vec::new());
+                }
+
+                FieldValue::Bytes(std::slice::from_raw_parts(data, fvalue_get_bytes_size(fv)).to_vec())
+            }
+            Some(FieldType::IPv4) => {
+                let ipv4 = fvalue_get_ipv4(fv);
+                FieldValue::Address(IpAddr::V4(Ipv4Addr::from((*ipv4).addr)))
+            }
+            Some(FieldType::IPv6) => {
+                let ipv6 = fvalue_get_ipv6(fv);
+                FieldValue::Address(IpAddr::V6(Ipv6Addr::from((*ipv6).addr.bytes)))
+            }
+            // Times that a system time can't represent are read as their text instead
+            Some(FieldType::AbsoluteTime) => match nstime_to_system_time(fvalue_get_time(fv)) {
+                Some(time) => FieldValue::AbsoluteTime(time),
+                None => FieldValue::Display(fvalue_display_text(fv)),
+            },
+            Some(FieldType::RelativeTime) => FieldValue::RelativeTime(nstime_to_duration(fvalue_get_time(fv))),
+            _ => FieldValue::Display(fvalue_display_text(fv)),
+        }
+    }
+}
+
+// Get the text Wireshark displays for the value of a field
+unsafe fn fvalue_display_text(fv: *mut fvalue_t) -> String {
+    let text = fvalue_to_string_repr(std::ptr::null_mut(), fv, ftrepr_FTREPR_DISPLAY, BASE_NONE as c_int);
+    if text.is_null() {
+        return String::new();
+    }
+
+    let value = CStr::from_ptr(text).to_string_lossy().into_owned();
+    wmem_free(std::ptr::null_mut(), text as *mut c_void);
+    value
+}
+
+// Convert a time since the epoch to a system time. The nanoseconds can be negative, such as for times before the
+// epoch, so the time is first split into whole seconds and a positive fraction of a second. Returns None if the
+// time is outside of the range of a system time.
+pub(crate) unsafe fn nstime_to_system_time(time: *const nstime_t) -> Option<SystemTime> {
+    let nsecs = (*time).nsecs as i64;
+    let secs = ((*time).secs as i64).saturating_add(nsecs.div_euclid(1_000_000_000));
+    let base = if secs < 0 {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+    } else {
+        UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))?
+    };
+
+    base.checked_add(Duration::from_nanos(nsecs.rem_euclid(1_000_000_000) as u64))
+}
+
+// Convert a time to a duration, clamping negative times to zero
+pub(crate) unsafe fn nstime_to_duration(time: *const nstime_t) -> Duration {
+    let nsecs = (*time).nsecs as i64;
+    let secs = ((*time).secs as i64).saturating_add(nsecs.div_euclid(1_000_000_000));
+    if secs < 0 {
+        return Duration::ZERO;
+    }
+
+    Duration::new(secs as u64, nsecs.rem_euclid(1_000_000_000) as u32)
+}
+
+// Read the values of every occurrence of a field in the packet tree, in the order they were added. Fields of
+// lower layers are only in the tree if the tree is being built, or if they were primed, such as by a filter or
+// by the wanted fields of a postdissector.
+pub(crate) unsafe fn read_field_values(tree: *mut _proto_node, hf_id: c_int) -> Vec<FieldValue> {
+    if tree.is_null() {
+        return Vec::new();
+    }
+
+    let finfos = proto_find_finfo(tree, hf_id);
+    if finfos.is_null() {
+        return Vec::new();
+    }
+
+    let values = std::slice::from_raw_parts((*finfos).pdata as *const *mut field_info, (*finfos).len as usize)
+        .iter()
+        .map(|finfo| FieldValue::from_field_info(*finfo))
+        .collect();
+
+    g_ptr_array_free(finfos, 1);
+    values
+}
+
+// Find the ID of a field of any protocol by its abbreviation, such as "ip.ttl". Returns None if no protocol
+// registered the field, such as when the protocol is not part of this build of Wireshark.
+pub(crate) unsafe fn find_field_id(abbrev: &str) -> Option<c_int> {
+    let name = CString::new(abbrev).unwrap();
+    let hf_id = proto_registrar_get_id_byname(name.as_ptr());
+    if hf_id < 0 {
+        return None;
+    }
+
+    Some(hf_id)
+}
@@ -0,0 +1,214 @@
+use epan_sys::*;
+use std::{ffi::*, ptr::null_mut};
+
+use crate::slots::{claim_slot, slot_dispatchers, MAX_SLOTS};
+use crate::tap::{tap_record_tap_id, tap_record_value};
+use crate::wireshark_protocol::{get_global_plugin, WiresharkPlugin, WiresharkProtocol};
+
+// The key the stream index of a frame is stored under in the packet's protocol data. Key 0 holds the ProtoDataMap.
+const FOLLOW_STREAM_KEY: u32 = 1;
+
+// The direction of a payload within a followed stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowDirection {
+    // Sent by the side that started the stream
+    ClientToServer,
+    // Sent by the other side
+    ServerToClient,
+}
+
+// A payload emitted with DissectorSubTree::follow_stream_payload, queued to the protocol's tap as a record so the
+// Follow Stream dialog and tshark -z follow can show it
+pub(crate) struct FollowPayload {
+    pub(crate) stream: u32,
+    pub(crate) direction: FollowDirection,
+    pub(crate) payload: Vec<u8>,
+}
+
+// Wireshark does not pass any context to the filter functions of a follower, so each protocol needs its own functions
+static FOLLOW_CONV_FILTER_DISPATCHERS: [follow_conv_filter_func; MAX_SLOTS] =
+    slot_dispatchers!(follow_conv_filter_dispatcher);
+
+static FOLLOW_INDEX_FILTER_DISPATCHERS: [follow_index_filter_func; MAX_SLOTS] =
+    slot_dispatchers!(follow_index_filter_dispatcher);
+
+static FOLLOW_STREAM_COUNT_DISPATCHERS: [follow_stream_count_func; MAX_SLOTS] =
+    slot_dispatchers!(follow_stream_count_dispatcher);
+
+// Register the protocol with the Follow Stream menu. The protocol's tap, named after its filter, carries the payloads.
+pub(crate) unsafe fn register_follow(plugin: &mut WiresharkPlugin, proto_id: &str, proto_handle: c_int, tap: *const c_char) {
+    let slot = claim_slot(
+        plugin.follow_streams.len(),
+        &format!("Protocol {}", proto_id),
+        "protocols with Follow Stream support",
+    );
+
+    // The stream counts are reset whenever a capture file is opened or dissected again
+    if slot == 0 {
+        register_init_routine(Some(follow_init_dispatcher));
+    }
+
+    plugin.follow_streams.push(proto_id.to_string());
+
+    register_follow_stream(
+        proto_handle,
+        tap,
+        FOLLOW_CONV_FILTER_DISPATCHERS[slot],
+        FOLLOW_INDEX_FILTER_DISPATCHERS[slot],
+        None,
+        Some(follow_port_to_display_dispatcher),
+        Some(follow_packet_dispatcher),
+        FOLLOW_STREAM_COUNT_DISPATCHERS[slot],
+        None,
+    );
+}
+
+// Remember the first stream a frame carries a payload of, so the stream of a selected frame can be followed
+pub(crate) unsafe fn remember_follow_stream(pinfo: *mut _packet_info, proto_handle: c_int, stream: u32) {
+    // The stream index is stored plus one, so a frame without a payload reads as null
+    if p_get_proto_data(wmem_file_scope(), pinfo, proto_handle, FOLLOW_STREAM_KEY).is_null() {
+        let stored = (stream as usize).saturating_add(1) as *mut c_void;
+        p_add_proto_data(wmem_file_scope(), pinfo, proto_handle, FOLLOW_STREAM_KEY, stored);
+    }
+}
+
+// Fill in the filter template of the protocol in the given slot with a stream index. Wireshark asks for the filter
+// right before it follows the stream, so the stream is also remembered as the one being followed.
+unsafe fn follow_filter(slot: usize, stream: c_uint) -> *mut c_char {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let proto = follow_protocol(&plugref, slot);
+    proto.set_followed_stream(stream);
+
+    let filter = proto.get_follow_filter_template().replace("{}", &stream.to_string());
+    let filter = CString::new(filter).unwrap();
+
+    // The filter is freed by Wireshark with g_free
+    g_strdup(filter.as_ptr())
+}
+
+fn follow_protocol<'a>(plugin: &'a WiresharkPlugin, slot: usize) -> &'a WiresharkProtocol {
+    plugin.get_registered_protocol(&plugin.follow_streams[slot])
+}
+
+// Called with the selected packet to build the filter of the stream it belongs to
+unsafe extern "C" fn follow_conv_filter_dispatcher<const SLOT: usize>(
+    _edt: *mut epan_dissect_t,
+    pinfo: *mut _packet_info,
+    stream: *mut c_uint,
+    sub_stream: *mut c_uint,
+) -> *mut c_char {
+    let proto_handle = {
+        let plugin = get_global_plugin();
+        let plugref = plugin.as_ref().borrow();
+        follow_protocol(&plugref, SLOT).get_proto_handle()
+    };
+
+    let stored = p_get_proto_data(wmem_file_scope(), pinfo, proto_handle, FOLLOW_STREAM_KEY) as usize;
+    if stored == 0 {
+        return null_mut();
+    }
+
+    let index = (stored - 1) as c_uint;
+    *stream = index;
+    *sub_stream = 0;
+
+    follow_filter(SLOT, index)
+}
+
+// Called with a stream index, such as the one given to tshark -z follow, to build the filter of the stream
+unsafe extern "C" fn follow_index_filter_dispatcher<const SLOT: usize>(stream: c_uint, _sub_stream: c_uint) -> *mut c_char {
+    follow_filter(SLOT, stream)
+}
+
+// Called to find out how many streams the protocol has seen
+unsafe extern "C" fn follow_stream_count_dispatcher<const SLOT: usize>() -> u32 {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    follow_protocol(&plugref, SLOT).get_follow_stream_count()
+}
+
+// Called when a capture file is opened or dissected again, to forget the streams of the previous pass
+unsafe extern "C" fn follow_init_dispatcher() {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+
+    for proto_id in plugref.follow_streams.iter() {
+        plugref.get_registered_protocol(proto_id).reset_follow_streams();
+    }
+}
+
+// Ports of plugshark protocols are shown as plain numbers
+unsafe extern "C" fn follow_port_to_display_dispatcher(allocator: *mut wmem_allocator_t, port: c_uint) -> *mut c_char {
+    let port = CString::new(port.to_string()).unwrap();
+    wmem_strdup(allocator, port.as_ptr())
+}
+
+// Copy an address into memory owned by the follow info, which frees it with g_free
+unsafe fn follow_copy_address(to: *mut address, from: *const address) {
+    let data = if (*from).len > 0 && !(*from).data.is_null() {
+        g_memdup2((*from).data, (*from).len as usize)
+    } else {
+        null_mut()
+    };
+
+    (*to).type_ = (*from).type_;
+    (*to).len = (*from).len;
+    (*to).data = data;
+    (*to).priv_ = data;
+}
+
+// Called by Wireshark for each record queued to the tap of a protocol while one of its streams is followed
+unsafe extern "C" fn follow_packet_dispatcher(
+    tapdata: *mut c_void,
+    pinfo: *mut _packet_info,
+    _edt: *mut epan_dissect_t,
+    data: *const c_void,
+    _flags: tap_flags_t,
+) -> tap_packet_status {
+    let payload = match tap_record_value(data).and_then(|value| value.downcast_ref::<FollowPayload>()) {
+        Some(payload) => payload,
+        None => return tap_packet_status_TAP_PACKET_DONT_REDRAW,
+    };
+
+    // A frame can carry payloads of several streams, only those of the followed stream are shown
+    let followed_stream = {
+        let plugin = get_global_plugin();
+        let plugref = plugin.as_ref().borrow();
+        tap_record_tap_id(data)
+            .and_then(|tap_id| plugref.get_protocol_by_tap_id(tap_id))
+            .and_then(|proto| proto.get_followed_stream())
+    };
+    if followed_stream.is_some_and(|stream| stream != payload.stream) {
+        return tap_packet_status_TAP_PACKET_DONT_REDRAW;
+    }
+    let follow_info = tapdata as *mut follow_info_t;
+    let is_server = payload.direction == FollowDirection::ServerToClient;
+
+    // The first payload tells which side of the packet is the client
+    if (*follow_info).client_port == 0 && (*follow_info).client_ip.type_ == address_type_AT_NONE as c_int {
+        let (client, server, client_port, server_port) = if is_server {
+            (&(*pinfo).dst, &(*pinfo).src, (*pinfo).destport, (*pinfo).srcport)
+        } else {
+            (&(*pinfo).src, &(*pinfo).dst, (*pinfo).srcport, (*pinfo).destport)
+        };
+
+        follow_copy_address(&mut (*follow_info).client_ip, client);
+        follow_copy_address(&mut (*follow_info).server_ip, server);
+        (*follow_info).client_port = client_port;
+        (*follow_info).server_port = server_port;
+    }
+
+    // The record and everything it points to is freed by the follow info
+    let record = g_malloc0(std::mem::size_of::<follow_record_t>()) as *mut follow_record_t;
+    (*record).is_server = is_server;
+    (*record).packet_num = (*pinfo).num;
+    (*record).abs_ts = (*pinfo).abs_ts;
+    (*record).data = g_byte_array_sized_new(payload.payload.len() as c_uint);
+    g_byte_array_append((*record).data, payload.payload.as_ptr(), payload.payload.len() as c_uint);
+
+    (*follow_info).bytes_written[is_server as usize] += payload.payload.len() as u32;
+    (*follow_info).payload = g_list_prepend((*follow_info).payload, record as *mut c_void);
+
+    tap_packet_status_TAP_PACKET_DONT_REDRAW
+}
@@ -3,6 +3,33 @@
 mod wireshark_protocol;
 pub use wireshark_protocol::*;
 
+mod preferences;
+pub use preferences::*;
+
+mod slots;
+
+mod custom_format;
+
+mod export_object;
+
+mod follow;
+pub use follow::*;
+
+mod field_value;
+pub use field_value::*;
+
+mod tap;
+pub use tap::*;
+
+mod stats_tree;
+pub use stats_tree::*;
+
+mod address_type;
+pub use address_type::*;
+
+mod conversation_table;
+pub use conversation_table::*;
+
 mod defines;
 
 #[allow(unused_imports)]
@@ -0,0 +1,337 @@
+use epan_sys::*;
+use std::{any::TypeId, ffi::*, ptr::{null, null_mut}};
+
+use crate::wireshark_protocol::{WiresharkPlugin, WiresharkProtocol};
+
+// Implemented by Rust enums that are used as the value of an enum preference
+pub trait PreferenceEnum: Copy + PartialEq + 'static {
+    // All values of the enum, each with the short name stored in the preferences file and the
+    // description shown in the preferences dialog
+    fn variants() -> &'static [(Self, &'static str, &'static str)];
+}
+
+// Describes a preference that is going to be registered with a protocol
+pub struct WiresharkPreferenceArgs<'a> {
+    // The name of the preference, used in the preferences file and to read the value
+    name: &'a str,
+
+    // The title shown in the preferences dialog
+    title: &'a str,
+
+    // The longer description shown in the preferences dialog
+    description: &'a str,
+
+    // The type and default value of the preference
+    kind: Option<PreferenceKind>,
+}
+
+impl<'a> WiresharkPreferenceArgs<'a> {
+    // Create a new preference definition
+    pub fn new(name: &'a str, title: &'a str) -> Self {
+        Self {
+            name: name,
+            title: title,
+            description: "",
+            kind: None,
+        }
+    }
+
+    // Add a description to the preference definition
+    pub fn with_description(mut self, description: &'a str) -> Self {
+        self.description = description;
+        self
+    }
+
+    // Make this an unsigned integer preference, displayed in the given base (10 or 16)
+    pub fn with_uint(mut self, default: u32, base: u32) -> Self {
+        self.kind = Some(PreferenceKind::UInt { default: default, base: base });
+        self
+    }
+
+    // Make this a checkbox preference
+    pub fn with_bool(mut self, default: bool) -> Self {
+        self.kind = Some(PreferenceKind::Bool { default: default });
+        self
+    }
+
+    // Make this a free text preference
+    pub fn with_string(mut self, default: &str) -> Self {
+        self.kind = Some(PreferenceKind::String { default: default.to_string() });
+        self
+    }
+
+    // Make this a preference that holds the path to a file. If for_writing is true the file
+    // dialog allows choosing a file that does not exist yet.
+    pub fn with_filename(mut self, default: &str, for_writing: bool) -> Self {
+        self.kind = Some(PreferenceKind::Filename {
+            default: default.to_string(),
+            for_writing: for_writing,
+        });
+        self
+    }
+
+    // Make this a preference that selects one value of a Rust enum
+    pub fn with_enum<E: PreferenceEnum>(mut self, default: E) -> Self {
+        let variants = E::variants();
+        let default_index = variants
+            .iter()
+            .position(|(value, _, _)| *value == default)
+            .expect("Default value of enum preference is not one of its variants");
+
+        self.kind = Some(PreferenceKind::Enum {
+            type_id: TypeId::of::<E>(),
+            default: default_index as c_int,
+            variants: variants
+                .iter()
+                .map(|(_, name, description)| (name.to_string(), description.to_string()))
+                .collect(),
+        });
+        self
+    }
+
+    // Make this a preference that holds a set of ranges, such as "1234,2000-2010". All values
+    // must be less than or equal to max_value.
+    pub fn with_range(mut self, default: &str, max_value: u32) -> Self {
+        self.kind = Some(PreferenceKind::Range {
+            default: default.to_string(),
+            max_value: max_value,
+        });
+        self
+    }
+
+    pub(crate) fn into_definition(self) -> WiresharkPreferenceDefinition {
+        let kind = self
+            .kind
+            .expect(format!("Preference {} has no type, use one of the with_ functions to set it.", self.name).as_str());
+
+        WiresharkPreferenceDefinition {
+            name: self.name.to_string(),
+            title: self.title.to_string(),
+            description: self.description.to_string(),
+            kind: kind,
+        }
+    }
+}
+
+// The type and default value of a preference
+pub(crate) enum PreferenceKind {
+    UInt { default: u32, base: u32 },
+    Bool { default: bool },
+    String { default: String },
+    Filename { default: String, for_writing: bool },
+    Enum { type_id: TypeId, default: c_int, variants: Vec<(String, String)> },
+    Range { default: String, max_value: u32 },
+}
+
+pub(crate) struct WiresharkPreferenceDefinition {
+    pub(crate) name: String,
+    title: String,
+    description: String,
+    kind: PreferenceKind,
+}
+
+// Where Wireshark stores the current value of a preference. All pointers are allocated in the global
+// allocator and are written by Wireshark whenever the preference changes.
+pub(crate) enum PreferenceStorage {
+    UInt(*mut c_uint),
+    Bool(*mut bool),
+    String(*mut *const c_char),
+    Enum(TypeId, *mut c_int),
+    Range(*mut *mut range_t),
+}
+
+pub(crate) struct PreferenceHandle {
+    pub(crate) name: String,
+    pub(crate) storage: PreferenceStorage,
+}
+
+impl WiresharkPreferenceDefinition {
+    // Register the preference to the given preferences module
+    pub(crate) unsafe fn register(self, plugin: &WiresharkPlugin, module: *mut module_t) -> PreferenceHandle {
+        let name = plugin.alloc_string(&self.name);
+        let title = plugin.alloc_string(&self.title);
+        let description = plugin.alloc_string(&self.description);
+
+        let storage = match self.kind {
+            PreferenceKind::UInt { default, base } => {
+                let var = plugin.alloc_value(default as c_uint);
+                prefs_register_uint_preference(module, name, title, description, base, var);
+                PreferenceStorage::UInt(var)
+            }
+            PreferenceKind::Bool { default } => {
+                let var = plugin.alloc_value(default);
+                prefs_register_bool_preference(module, name, title, description, var);
+                PreferenceStorage::Bool(var)
+            }
+            PreferenceKind::String { default } => {
+                // Wireshark makes its own copy of the default value
+                let var = plugin.alloc_value(plugin.alloc_string(&default) as *const c_char);
+                prefs_register_string_preference(module, name, title, description, var);
+                PreferenceStorage::String(var)
+            }
+            PreferenceKind::Filename { default, for_writing } => {
+                let var = plugin.alloc_value(plugin.alloc_string(&default) as *const c_char);
+                prefs_register_filename_preference(module, name, title, description, var, for_writing);
+                PreferenceStorage::String(var)
+            }
+            PreferenceKind::Enum { type_id, default, variants } => {
+                // The enum values are a flat C array terminated by a null entry
+                let mut values: Vec<enum_val_t> = variants
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (value_name, value_description))| enum_val_t {
+                        name: plugin.alloc_string(value_name),
+                        description: plugin.alloc_string(value_description),
+                        value: index as c_int,
+                    })
+                    .collect();
+                values.push(enum_val_t {
+                    name: null(),
+                    description: null(),
+                    value: -1,
+                });
+                let values_ptr = plugin.alloc_flat_c_array_iter(values);
+
+                let var = plugin.alloc_value(default);
+                prefs_register_enum_preference(module, name, title, description, var, values_ptr, false);
+                PreferenceStorage::Enum(type_id, var)
+            }
+            PreferenceKind::Range { default, max_value } => {
+                let var = plugin.alloc_value(null_mut::<range_t>());
+                range_convert_str(wmem_epan_scope(), var, plugin.alloc_string(&default), max_value);
+                prefs_register_range_preference(module, name, title, description, var, max_value);
+                PreferenceStorage::Range(var)
+            }
+        };
+
+        PreferenceHandle {
+            name: self.name,
+            storage: storage,
+        }
+    }
+}
+
+// This callback is called after the preferences of the protocol have been applied, such as when they were
+// read at startup or changed by the user. It is only called for the protocol whose preferences were applied.
+pub type PreferencesCallback = unsafe fn(Preferences<'_>);
+
+// Gives access to the current values of a protocol's preferences. The values are owned by the protocol and live
+// as long as the plugin, so they can be read at any time.
+pub struct Preferences<'a> {
+    proto: &'a WiresharkProtocol,
+}
+
+impl<'a> Preferences<'a> {
+    pub(crate) fn new(proto: &'a WiresharkProtocol) -> Self {
+        Self { proto: proto }
+    }
+
+    // Get the current value of an unsigned integer preference
+    pub fn get_uint(&self, name: &str) -> u32 {
+        match self.proto.get_preference(name).storage {
+            PreferenceStorage::UInt(var) => unsafe { *var },
+            _ => panic!("Preference {} is not an unsigned integer preference.", name),
+        }
+    }
+
+    // Get the current value of a checkbox preference
+    pub fn get_bool(&self, name: &str) -> bool {
+        match self.proto.get_preference(name).storage {
+            PreferenceStorage::Bool(var) => unsafe { *var },
+            _ => panic!("Preference {} is not a bool preference.", name),
+        }
+    }
+
+    // Get the current value of a string or filename preference
+    pub fn get_string(&self, name: &str) -> String {
+        match self.proto.get_preference(name).storage {
+            PreferenceStorage::String(var) => unsafe {
+                if (*var).is_null() {
+                    return String::new();
+                }
+
+                CStr::from_ptr(*var).to_string_lossy().into_owned()
+            },
+            _ => panic!("Preference {} is not a string preference.", name),
+        }
+    }
+
+    // Get the current value of an enum preference
+    pub fn get_enum<E: PreferenceEnum>(&self, name: &str) -> E {
+        match self.proto.get_preference(name).storage {
+            PreferenceStorage::Enum(type_id, var) => {
+                if type_id != TypeId::of::<E>() {
+                    panic!("Preference {} was registered with a different enum type.", name);
+                }
+
+                E::variants()[unsafe { *var } as usize].0
+            }
+            _ => panic!("Preference {} is not an enum preference.", name),
+        }
+    }
+
+    // Get the current value of a range preference. The ranges are copied, as Wireshark frees the range when the
+    // preference changes.
+    pub fn get_range(&self, name: &str) -> PreferenceRange {
+        let range = unsafe { *self.get_range_ptr(name) };
+        if range.is_null() {
+            return PreferenceRange { ranges: Vec::new() };
+        }
+
+        let ranges = unsafe {
+            (*range)
+                .ranges
+                .as_slice((*range).nranges as usize)
+                .iter()
+                .map(|range| (range.low, range.high))
+                .collect()
+        };
+
+        PreferenceRange { ranges: ranges }
+    }
+
+    // Replace the matches this protocol registered in the given dissector table, such as "udp.port", with the
+    // current value of a range preference. Used to re-register ports after the user changes them.
+    pub unsafe fn update_match_range(&self, table: &str, range_name: &str) {
+        let handle = self.proto.get_dissector_handle();
+        if handle.is_null() {
+            return;
+        }
+
+        let range = range_copy(wmem_epan_scope(), *self.get_range_ptr(range_name));
+        let mut applied_ranges = self.proto.applied_ranges.borrow_mut();
+
+        // Remove the range that was registered the last time
+        if let Some(old_range) = applied_ranges.remove(table) {
+            dissector_delete_uint_range(self.proto.to_temp_cstring_fast(table), old_range, handle);
+            wmem_free(wmem_epan_scope(), old_range as *mut c_void);
+        }
+
+        dissector_add_uint_range(self.proto.to_temp_cstring_fast(table), range, handle);
+        applied_ranges.insert(table.to_string(), range);
+    }
+
+    fn get_range_ptr(&self, name: &str) -> *mut *mut range_t {
+        match self.proto.get_preference(name).storage {
+            PreferenceStorage::Range(var) => var,
+            _ => panic!("Preference {} is not a range preference.", name),
+        }
+    }
+}
+
+// The value of a range preference
+pub struct PreferenceRange {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl PreferenceRange {
+    // Returns true if the value is within any of the ranges
+    pub fn contains(&self, value: u32) -> bool {
+        self.ranges.iter().any(|&(low, high)| low <= value && value <= high)
+    }
+
+    // Get all ranges as inclusive (low, high) pairs
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges
+    }
+}
@@ -0,0 +1,30 @@
+// Wireshark does not pass any context to some callbacks, such as custom formatting functions and the filter
+// functions of a follower. For those, a table of functions is generated ahead of time, where each function is
+// generic over the slot it belongs to, and every user of the callback claims the next free slot.
+
+// Maximum number of slots in a table
+pub(crate) const MAX_SLOTS: usize = 64;
+
+// Generates a table holding one function for each slot
+macro_rules! slot_dispatchers {
+    ($dispatcher:ident) => {
+        slot_dispatchers!($dispatcher,
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+            32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63)
+    };
+    ($dispatcher:ident, $($slot:literal)*) => {
+        [$(Some($dispatcher::<$slot>),)*]
+    };
+}
+
+pub(crate) use slot_dispatchers;
+
+// Claim the next slot of a table with the given number of slots in use. The owner and kind describe the user of
+// the slot, such as "Field myproto.flags" and "fields with custom formatting", if the table is full.
+pub(crate) fn claim_slot(used: usize, owner: &str, kind: &str) -> usize {
+    if used >= MAX_SLOTS {
+        panic!("{} exceeds the maximum of {} {}.", owner, MAX_SLOTS, kind);
+    }
+
+    used
+}
@@ -0,0 +1,288 @@
+use epan_sys::*;
+use std::{any::Any, ffi::*};
+
+use crate::tap::{is_plugshark_tap, tap_record_value};
+use crate::wireshark_protocol::{get_global_plugin, WiresharkPlugin};
+
+// Describes a statistics tree that is shown in the Statistics menu and with tshark -z <abbr>,tree
+pub struct WiresharkStatsTreeArgs<'a> {
+    // The name of the tap the tree reads records from, which is the filter name of the plugshark protocol queueing
+    // them. Taps of protocols that are not plugshark protocols can't be used.
+    tap: &'a str,
+
+    // The abbreviation used on the command line, such as "myproto" for tshark -z myproto,tree
+    abbr: &'a str,
+
+    // The name shown in the Statistics menu. Use / to place it in a submenu, such as "MyProto/Packet Types".
+    name: &'a str,
+
+    // All nodes that are created when the tree is opened, in the order they are created
+    nodes: Vec<StatsNodeDefinition>,
+}
+
+impl<'a> WiresharkStatsTreeArgs<'a> {
+    // Create a new statistics tree definition
+    pub fn new(tap: &'a str, abbr: &'a str, name: &'a str) -> Self {
+        Self {
+            tap: tap,
+            abbr: abbr,
+            name: name,
+            nodes: Vec::new(),
+        }
+    }
+
+    // Add a node that counts packets. Children can be added to it during the packet callback with tick_child. The
+    // parent is the name of a node added before, or None for the top level of the tree.
+    pub fn with_node(mut self, name: &str, parent: Option<&str>) -> Self {
+        self.add_node(name, parent, StatsNodeKind::Counter);
+        self
+    }
+
+    // Add a node that counts packets, with one child for each distinct value passed to tick_pivot
+    pub fn with_pivot(mut self, name: &str, parent: Option<&str>) -> Self {
+        self.add_node(name, parent, StatsNodeKind::Pivot);
+        self
+    }
+
+    // Add a node that counts packets in buckets, such as "0-99", "100-499" and "500-". Open-ended buckets are
+    // written as "500-" or "-99".
+    pub fn with_range(mut self, name: &str, parent: Option<&str>, ranges: &[&str]) -> Self {
+        self.add_node(
+            name,
+            parent,
+            StatsNodeKind::Range(ranges.iter().map(|range| range.to_string()).collect()),
+        );
+        self
+    }
+
+    fn add_node(&mut self, name: &str, parent: Option<&str>, kind: StatsNodeKind) {
+        // Wireshark finds nodes by name anywhere in the tree, so they must be unique
+        if self.nodes.iter().any(|node| node.name == name) {
+            panic!("Node {} already exists in statistics tree {}.", name, self.abbr);
+        }
+
+        if let Some(parent) = parent {
+            if !self.nodes.iter().any(|node| node.name == parent) {
+                panic!(
+                    "Parent {} of node {} must be added to statistics tree {} first.",
+                    parent, name, self.abbr
+                );
+            }
+        }
+
+        self.nodes.push(StatsNodeDefinition {
+            name: name.to_string(),
+            parent: parent.map(|parent| parent.to_string()),
+            kind: kind,
+        });
+    }
+}
+
+enum StatsNodeKind {
+    Counter,
+    Pivot,
+    Range(Vec<String>),
+}
+
+struct StatsNodeDefinition {
+    name: String,
+    parent: Option<String>,
+    kind: StatsNodeKind,
+}
+
+// This callback is called for each record of the given type that is queued to the tap of the statistics tree.
+// It is passed the tree and updates its nodes.
+pub type StatsTreeCallback<T> = unsafe fn(StatsTree<'_>, &T);
+
+// A statistics tree with its record type erased, kept in the plugin so the dispatchers can find it by abbreviation
+pub(crate) struct WiresharkStatsTree {
+    tap: String,
+    pub(crate) abbr: String,
+    name: String,
+    nodes: Vec<StatsNodeDefinition>,
+    packet_fn: Box<dyn Fn(StatsTree<'_>, &dyn Any) -> bool>,
+}
+
+impl WiresharkStatsTree {
+    pub(crate) fn new<T: 'static>(args: WiresharkStatsTreeArgs<'_>, packet_fn: StatsTreeCallback<T>) -> Self {
+        Self {
+            tap: args.tap.to_string(),
+            abbr: args.abbr.to_string(),
+            name: args.name.to_string(),
+            nodes: args.nodes,
+            packet_fn: Box::new(move |tree, record| match record.downcast_ref::<T>() {
+                Some(record) => {
+                    unsafe { packet_fn(tree, record) };
+                    true
+                }
+                None => false,
+            }),
+        }
+    }
+
+    // Register the statistics tree with Wireshark
+    pub(crate) unsafe fn register(&self, plugin: &WiresharkPlugin) {
+        if !is_plugshark_tap(&self.tap) {
+            panic!("Tap {} of statistics tree {} is not the tap of a plugshark protocol.", self.tap, self.abbr);
+        }
+
+        stats_tree_register_plugin(
+            plugin.alloc_string(&self.tap),
+            plugin.alloc_string(&self.abbr),
+            plugin.alloc_string(&self.name),
+            0,
+            Some(stats_tree_packet_dispatcher),
+            Some(stats_tree_init_dispatcher),
+            None,
+        );
+    }
+
+    fn get_node(&self, name: &str) -> &StatsNodeDefinition {
+        self.nodes
+            .iter()
+            .find(|node| node.name == name)
+            .expect(format!("Node {} does not exist in statistics tree {}.", name, self.abbr).as_str())
+    }
+}
+
+// A statistics tree that is being updated with the record of a packet
+pub struct StatsTree<'a> {
+    st: *mut stats_tree,
+    tree: &'a WiresharkStatsTree,
+}
+
+impl<'a> StatsTree<'a> {
+    // Increment the count of a node added with with_node
+    pub unsafe fn tick(&mut self, node: &str) {
+        let definition = self.tree.get_node(node);
+        match definition.kind {
+            StatsNodeKind::Counter => {}
+            _ => panic!("Node {} is not a counter node, use its own tick function.", node),
+        }
+
+        let parent_id = self.parent_id(definition.parent.as_deref());
+        self.manip_node(manip_node_mode_MN_INCREASE, node, parent_id, 1);
+    }
+
+    // Increment the count of the child with the given name under a node added with with_node, creating the
+    // child the first time it is used
+    pub unsafe fn tick_child(&mut self, node: &str, child: &str) {
+        let parent_id = self.node_id(node);
+        self.manip_node(manip_node_mode_MN_INCREASE, child, parent_id, 1);
+    }
+
+    // Add a value to the average, minimum and maximum of the child with the given name under a node added
+    // with with_node, creating the child the first time it is used
+    pub unsafe fn average_child(&mut self, node: &str, child: &str, value: i32) {
+        let parent_id = self.node_id(node);
+        self.manip_node(manip_node_mode_MN_AVERAGE, child, parent_id, value);
+    }
+
+    // Increment the count of the bucket of a node added with with_range that contains the value
+    pub unsafe fn tick_range(&mut self, node: &str, value: i32) {
+        let definition = self.tree.get_node(node);
+        match definition.kind {
+            StatsNodeKind::Range(_) => {}
+            _ => panic!("Node {} is not a range node.", node),
+        }
+
+        let parent_id = self.parent_id(definition.parent.as_deref());
+        let name = CString::new(node).unwrap();
+        stats_tree_tick_range(self.st, name.as_ptr(), parent_id, value);
+    }
+
+    // Increment the count of a node added with with_pivot, and of its child for the given value
+    pub unsafe fn tick_pivot(&mut self, node: &str, value: &str) {
+        match self.tree.get_node(node).kind {
+            StatsNodeKind::Pivot => {}
+            _ => panic!("Node {} is not a pivot node.", node),
+        }
+
+        let value = CString::new(value).unwrap();
+        stats_tree_tick_pivot(self.st, self.node_id(node), value.as_ptr());
+    }
+
+    // Get the ID Wireshark gave to a node when it was created
+    unsafe fn node_id(&self, name: &str) -> c_int {
+        // Make sure the node was declared, Wireshark returns the top level for unknown names
+        self.tree.get_node(name);
+
+        let name = CString::new(name).unwrap();
+        stats_tree_parent_id_by_name(self.st, name.as_ptr())
+    }
+
+    unsafe fn parent_id(&self, parent: Option<&str>) -> c_int {
+        match parent {
+            Some(parent) => self.node_id(parent),
+            None => 0,
+        }
+    }
+
+    unsafe fn manip_node(&mut self, mode: manip_node_mode, name: &str, parent_id: c_int, value: c_int) {
+        let name = CString::new(name).unwrap();
+        stats_tree_manip_node_int(mode, self.st, name.as_ptr(), parent_id, false, value);
+    }
+}
+
+// Get the abbreviation of the statistics tree Wireshark is calling back for
+unsafe fn stats_tree_abbr<'a>(st: *mut stats_tree) -> &'a str {
+    CStr::from_ptr((*(*st).cfg).abbr).to_str().unwrap()
+}
+
+// Called by Wireshark whenever a statistics tree is opened, to create its nodes
+unsafe extern "C" fn stats_tree_init_dispatcher(st: *mut stats_tree) {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let tree = plugref.get_stats_tree(stats_tree_abbr(st));
+
+    let stats_tree = StatsTree { st: st, tree: tree };
+    for node in tree.nodes.iter() {
+        let name = CString::new(node.name.as_str()).unwrap();
+        let parent_id = stats_tree.parent_id(node.parent.as_deref());
+
+        match &node.kind {
+            StatsNodeKind::Counter => {
+                stats_tree_create_node(st, name.as_ptr(), parent_id, stat_node_datatype_STAT_DT_INT, true);
+            }
+            StatsNodeKind::Pivot => {
+                stats_tree_create_pivot(st, name.as_ptr(), parent_id);
+            }
+            StatsNodeKind::Range(ranges) => {
+                let ranges: Vec<CString> = ranges.iter().map(|range| CString::new(range.as_str()).unwrap()).collect();
+                let mut range_ptrs: Vec<*mut c_char> = ranges.iter().map(|range| range.as_ptr() as *mut c_char).collect();
+
+                stats_tree_create_range_node_string(
+                    st,
+                    name.as_ptr(),
+                    parent_id,
+                    range_ptrs.len() as c_int,
+                    range_ptrs.as_mut_ptr(),
+                );
+            }
+        }
+    }
+}
+
+// Called by Wireshark for each record queued to the tap of an open statistics tree
+unsafe extern "C" fn stats_tree_packet_dispatcher(
+    st: *mut stats_tree,
+    _pinfo: *mut _packet_info,
+    _edt: *mut epan_dissect_t,
+    data: *const c_void,
+    _flags: tap_flags_t,
+) -> tap_packet_status {
+    let record = match tap_record_value(data) {
+        Some(record) => record,
+        None => return tap_packet_status_TAP_PACKET_DONT_REDRAW,
+    };
+
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let tree = plugref.get_stats_tree(stats_tree_abbr(st));
+
+    if (tree.packet_fn)(StatsTree { st: st, tree: tree }, record) {
+        tap_packet_status_TAP_PACKET_REDRAW
+    } else {
+        tap_packet_status_TAP_PACKET_DONT_REDRAW
+    }
+}
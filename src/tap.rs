@@ -0,0 +1,188 @@
+use epan_sys::*;
+use std::{any::Any, ffi::*, ptr::null};
+
+// Receives the records a protocol queues to its tap with DissectorSubTree::queue_tap. Listeners can be added
+// for taps of this plugin or of any other plugshark plugin. Records are matched to the listener by their Rust
+// type, so a listener on a tap of another plugin only receives records if both plugins were built with the same
+// compiler and the same version of the crate defining the record type. Taps of other Wireshark protocols, such as
+// "tcp" or "http", don't queue plugshark records and can't be listened to.
+pub trait TapListener: 'static {
+    // The type of record queued by the protocol. Records of any other type are ignored.
+    type Record: 'static;
+
+    // Called before the capture file is (re)dissected, to clear any state from a previous pass
+    fn reset(&mut self) {}
+
+    // Called once for each record queued to the tap. Returns true if the results changed and should be drawn.
+    fn packet(&mut self, record: &Self::Record) -> bool;
+
+    // Called when the results should be displayed, such as at the end of a tshark run
+    fn draw(&mut self) {}
+}
+
+// A tap listener with its record type erased, so listeners of different record types can be stored together
+pub(crate) trait ErasedTapListener {
+    fn reset(&mut self);
+    fn packet(&mut self, record: &dyn Any) -> bool;
+    fn draw(&mut self);
+}
+
+impl<L: TapListener> ErasedTapListener for L {
+    fn reset(&mut self) {
+        TapListener::reset(self)
+    }
+
+    fn packet(&mut self, record: &dyn Any) -> bool {
+        match record.downcast_ref::<L::Record>() {
+            Some(record) => TapListener::packet(self, record),
+            None => false,
+        }
+    }
+
+    fn draw(&mut self) {
+        TapListener::draw(self)
+    }
+}
+
+// Describes a tap listener that is going to be registered during handoff
+pub(crate) struct WiresharkTapListenerDefinition {
+    pub(crate) tap: String,
+    pub(crate) filter: Option<String>,
+    pub(crate) listener: Box<dyn ErasedTapListener>,
+}
+
+impl WiresharkTapListenerDefinition {
+    // Register the listener to its tap. The listener is kept for the lifetime of the plugin.
+    pub(crate) unsafe fn register(self) {
+        if !is_plugshark_tap(&self.tap) {
+            panic!("Tap {} is not the tap of a plugshark protocol.", self.tap);
+        }
+
+        let tap = CString::new(self.tap.as_str()).unwrap();
+        let filter = self.filter.map(|filter| CString::new(filter).unwrap());
+
+        // The listener is passed back to every callback, double boxed to make it a thin pointer
+        let listener: *mut Box<dyn ErasedTapListener> = Box::into_raw(Box::new(self.listener));
+
+        let error = register_tap_listener(
+            tap.as_ptr(),
+            listener as *mut c_void,
+            filter.as_ref().map_or(null(), |filter| filter.as_ptr()),
+            0,
+            Some(tap_reset_dispatcher),
+            Some(tap_packet_dispatcher),
+            Some(tap_draw_dispatcher),
+            None,
+        );
+
+        if !error.is_null() {
+            let message = CStr::from_ptr((*error).str_).to_string_lossy().into_owned();
+            g_string_free(error, 1);
+            drop(Box::from_raw(listener));
+
+            panic!("Could not register tap listener for {}: {}", self.tap, message);
+        }
+    }
+}
+
+// Wireshark does not know which taps queue plugshark records, and each plugshark plugin keeps its own state, so
+// the taps are marked with a GLib quark, which is shared by every plugin in the process
+fn plugshark_tap_quark_name(tap: &str) -> CString {
+    CString::new(format!("plugshark.tap.{}", tap)).unwrap()
+}
+
+// Mark the tap with the given name as a tap that only plugshark records are queued to
+pub(crate) unsafe fn mark_plugshark_tap(tap: &str) {
+    g_quark_from_string(plugshark_tap_quark_name(tap).as_ptr());
+}
+
+// Returns true if the tap with the given name was registered by a protocol of any plugshark plugin. Taps are
+// registered along with the protocols, so this is only reliable once all protocols are registered.
+pub(crate) unsafe fn is_plugshark_tap(tap: &str) -> bool {
+    g_quark_try_string(plugshark_tap_quark_name(tap).as_ptr()) != 0
+}
+
+// A record queued to a tap, which lives until the packet scope is freed
+struct TapRecord {
+    // The tap the record was queued to
+    tap_id: c_int,
+    value: Box<dyn Any>,
+}
+
+// Queue a record to the tap if anything is listening to it. The record is dropped once the packet has been
+// processed by all listeners.
+pub(crate) unsafe fn queue_tap_record<T: 'static>(tap_id: c_int, pinfo: *mut _packet_info, value: T) {
+    if !have_tap_listener(tap_id) {
+        return;
+    }
+
+    let record = Box::into_raw(Box::new(TapRecord {
+        tap_id: tap_id,
+        value: Box::new(value),
+    }));
+    wmem_register_callback((*pinfo).pool, Some(drop_tap_record), record as *mut c_void);
+
+    tap_queue_packet(tap_id, pinfo, record as *const c_void);
+}
+
+// Called by Wireshark when the packet scope a TapRecord was allocated in is freed
+unsafe extern "C" fn drop_tap_record(
+    _allocator: *mut wmem_allocator_t,
+    _event: wmem_cb_event_t,
+    user_data: *mut c_void,
+) -> bool {
+    drop(Box::from_raw(user_data as *mut TapRecord));
+
+    // Unregister the callback, the record is gone
+    false
+}
+
+// Get the record queued with queue_tap_record from the data pointer passed to a tap listener. The listener must
+// be registered to a tap marked with mark_plugshark_tap, as data queued to other taps is not a TapRecord.
+unsafe fn tap_record<'a>(data: *const c_void) -> Option<&'a TapRecord> {
+    if data.is_null() {
+        return None;
+    }
+
+    Some(&*(data as *const TapRecord))
+}
+
+// Get the value of a record queued with queue_tap_record from the data pointer passed to a tap listener
+pub(crate) unsafe fn tap_record_value<'a>(data: *const c_void) -> Option<&'a dyn Any> {
+    tap_record(data).map(|record| record.value.as_ref())
+}
+
+// Get the tap a record queued with queue_tap_record was queued to
+pub(crate) unsafe fn tap_record_tap_id(data: *const c_void) -> Option<c_int> {
+    tap_record(data).map(|record| record.tap_id)
+}
+
+unsafe extern "C" fn tap_reset_dispatcher(tapdata: *mut c_void) {
+    let listener = &mut *(tapdata as *mut Box<dyn ErasedTapListener>);
+    listener.reset();
+}
+
+unsafe extern "C" fn tap_packet_dispatcher(
+    tapdata: *mut c_void,
+    _pinfo: *mut _packet_info,
+    _edt: *mut epan_dissect_t,
+    data: *const c_void,
+    _flags: tap_flags_t,
+) -> tap_packet_status {
+    let listener = &mut *(tapdata as *mut Box<dyn ErasedTapListener>);
+    let record = match tap_record_value(data) {
+        Some(record) => record,
+        None => return tap_packet_status_TAP_PACKET_DONT_REDRAW,
+    };
+
+    if listener.packet(record) {
+        tap_packet_status_TAP_PACKET_REDRAW
+    } else {
+        tap_packet_status_TAP_PACKET_DONT_REDRAW
+    }
+}
+
+unsafe extern "C" fn tap_draw_dispatcher(tapdata: *mut c_void) {
+    let listener = &mut *(tapdata as *mut Box<dyn ErasedTapListener>);
+    listener.draw();
+}
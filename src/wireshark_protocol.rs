@@ -1,10 +1,21 @@
 use bumpalo::Bump;
 use epan_sys::*;
 use std::{
-    cell::{RefCell, RefMut}, collections::HashMap, ffi::*, io::Cursor, ptr::{null, null_mut}, rc::Rc
+    any::{Any, TypeId}, cell::{Cell, RefCell, RefMut}, collections::HashMap, ffi::*, io::Cursor, net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ptr::{null, null_mut}, rc::Rc, time::{Duration, SystemTime}
 };
 
 pub use crate::defines::*;
+use crate::address_type::*;
+use crate::conversation_table::*;
+use crate::custom_format::*;
+use crate::export_object::*;
+use crate::field_value::*;
+use crate::follow::*;
+use crate::preferences::*;
+use crate::slots::{claim_slot, slot_dispatchers, MAX_SLOTS};
+use crate::stats_tree::*;
+use crate::tap::*;
 use bytestream::{ByteOrder, StreamReader};
 
 pub type WiresharkProtocolRegistration = WiresharkProtocol;
@@ -25,10 +36,24 @@ static PROTO_PLUGIN: proto_plugin = proto_plugin {
 // tracker object that is shared between all callbacks instead and reference count it
 // to make Rust's borrow checker happy.
 #[allow(static_mut_refs)]
-unsafe fn get_global_plugin() -> Rc<RefCell<WiresharkPlugin>> {
+pub(crate) unsafe fn get_global_plugin() -> Rc<RefCell<WiresharkPlugin>> {
     PLUGIN.as_ref().unwrap().clone()
 }
 
+// Copy the text into a C buffer of the given size with a null terminator, truncating the text to fit without
+// splitting a UTF-8 character. The buffer must hold at least one byte. Returns the number of bytes copied, not
+// including the null terminator.
+pub(crate) unsafe fn write_truncated_cstring(text: &str, buf: *mut c_char, buf_len: usize) -> usize {
+    let mut length = text.len().min(buf_len - 1);
+    while !text.is_char_boundary(length) {
+        length -= 1;
+    }
+
+    std::ptr::copy_nonoverlapping(text.as_ptr(), buf as *mut u8, length);
+    *buf.add(length) = 0;
+    length
+}
+
 pub struct WiresharkPlugin {
     // Holds all memory that must survive the lifetime of the plugin
     global_alloc: Rc<RefCell<Bump>>,
@@ -39,6 +64,24 @@ pub struct WiresharkPlugin {
     // Holds all registered protocols
     protocols: HashMap<String, WiresharkProtocol>,
 
+    // Functions used to format fields with custom formatting, indexed by their dispatcher slot
+    pub(crate) custom_formats: Vec<WiresharkCustomFormat>,
+
+    // All tap listeners which are pending to be registered during handoff
+    tap_listeners: Option<Vec<WiresharkTapListenerDefinition>>,
+
+    // All statistics trees of the plugin, registered during handoff
+    stats_trees: Vec<WiresharkStatsTree>,
+
+    // All custom address types of the plugin, registered before the protocols
+    address_types: Vec<WiresharkAddressType>,
+
+    // IDs of the protocols with Follow Stream support, indexed by their dispatcher slot
+    pub(crate) follow_streams: Vec<String>,
+
+    // IDs of the protocols with a preferences module, indexed by their dispatcher slot
+    preference_modules: Vec<String>,
+
     // True if this plugin has been registered
     registered: bool,
 }
@@ -50,6 +93,12 @@ impl WiresharkPlugin {
             global_alloc: Rc::new(RefCell::new(Bump::new())),
             protocol_definitions: Some(HashMap::new()),
             protocols: HashMap::new(),
+            custom_formats: Vec::new(),
+            tap_listeners: Some(Vec::new()),
+            stats_trees: Vec::new(),
+            address_types: Vec::new(),
+            follow_streams: Vec::new(),
+            preference_modules: Vec::new(),
             registered: false,
         };
 
@@ -71,6 +120,79 @@ impl WiresharkPlugin {
         protos.insert(def.id.clone(), def);
     }
 
+    // Adds a listener to the tap with the given name, which is the filter name of the protocol queueing records to
+    // it. The tap can belong to a protocol of this plugin or of another plugshark plugin, see TapListener for what
+    // both plugins must share. If a filter is given, only records of packets matching the display filter are passed
+    // to the listener.
+    pub fn add_tap_listener<L: TapListener>(&mut self, tap: &str, filter: Option<&str>, listener: L) {
+        self.tap_listeners
+            .as_mut()
+            .expect("Tap listeners are already registered, add them during setup.")
+            .push(WiresharkTapListenerDefinition {
+                tap: tap.to_string(),
+                filter: filter.map(|filter| filter.to_string()),
+                listener: Box::new(listener),
+            });
+    }
+
+    // Adds a statistics tree to the Statistics menu and to tshark -z. The packet function is called with every
+    // record of type T that is queued to the tap of the tree.
+    pub fn add_stats_tree<'a, T: 'static>(&mut self, args: WiresharkStatsTreeArgs<'a>, packet_fn: StatsTreeCallback<T>) {
+        let tree = WiresharkStatsTree::new(args, packet_fn);
+
+        if self.stats_trees.iter().any(|existing| existing.abbr == tree.abbr) {
+            panic!("Statistics tree {} already registered, choose a different abbreviation.", tree.abbr);
+        }
+
+        self.stats_trees.push(tree);
+    }
+
+    // Adds an address type for endpoints that are not IP or MAC addresses. Dissectors use it to set the source and
+    // destination of a packet with DissectorSubTree::set_source_address and set_destination_address.
+    pub fn add_address_type<'a>(&mut self, args: WiresharkAddressTypeArgs<'a>) {
+        let address_type = WiresharkAddressType::new(self, args);
+
+        if self.address_types.iter().any(|existing| existing.name == address_type.name) {
+            panic!("Address type {} already registered, choose a different name.", address_type.name);
+        }
+
+        self.address_types.push(address_type);
+    }
+
+    // Gets an address type by its name
+    pub(crate) fn get_address_type(&self, name: &str) -> &WiresharkAddressType {
+        self.address_types
+            .iter()
+            .find(|address_type| address_type.name == name)
+            .expect(format!("Address type {} is not registered.", name).as_str())
+    }
+
+    // Gets an address type by the type Wireshark assigned to it
+    pub(crate) fn get_address_type_by_id(&self, type_id: c_int) -> &WiresharkAddressType {
+        self.address_types
+            .iter()
+            .find(|address_type| address_type.type_id == type_id)
+            .expect(format!("Address type {} does not belong to this plugin.", type_id).as_str())
+    }
+
+    // Registers all address types to Wireshark
+    unsafe fn register_address_types(&mut self) {
+        let mut address_types = std::mem::take(&mut self.address_types);
+        for address_type in address_types.iter_mut() {
+            address_type.register(self);
+        }
+
+        self.address_types = address_types;
+    }
+
+    // Gets a statistics tree by its abbreviation
+    pub(crate) fn get_stats_tree(&self, abbr: &str) -> &WiresharkStatsTree {
+        self.stats_trees
+            .iter()
+            .find(|tree| tree.abbr == abbr)
+            .expect(format!("Statistics tree {} is not registered.", abbr).as_str())
+    }
+
     // Gets the singleton instance of the plugin
     #[allow(static_mut_refs)]
     pub unsafe fn setup(setupfn: fn(RefMut<'_, WiresharkPlugin>)) {
@@ -107,13 +229,18 @@ impl WiresharkPlugin {
     }
 
     // Gets a protocol by its ID
-    fn get_registered_protocol(&self, id: &str) -> &WiresharkProtocol {
+    pub(crate) fn get_registered_protocol(&self, id: &str) -> &WiresharkProtocol {
         self.protocols.get(id).unwrap()
     }
 
+    // Gets the protocol that owns the given tap, if it belongs to this plugin
+    pub(crate) fn get_protocol_by_tap_id(&self, tap_id: c_int) -> Option<&WiresharkProtocol> {
+        self.protocols.values().find(|proto| proto.get_tap_id() == tap_id)
+    }
+
     // Allocates a C string in the global allocator. These strings are guarenteed to be valid
     // for the lifetime of the protocol.
-    fn alloc_string(&self, s: &str) -> *mut c_char {
+    pub(crate) fn alloc_string(&self, s: &str) -> *mut c_char {
         let alloc = self.global_alloc.clone();
         let borrow = alloc.as_ref().borrow();
 
@@ -121,7 +248,7 @@ impl WiresharkPlugin {
     }
 
     // Allocates a flat C array of the given iterator and returns the pointer to the first element
-    fn alloc_flat_c_array_iter<T, I>(&self, iter: I) -> *mut T
+    pub(crate) fn alloc_flat_c_array_iter<T, I>(&self, iter: I) -> *mut T
     where
         I: IntoIterator<Item = T>,
         I::IntoIter: ExactSizeIterator,
@@ -131,13 +258,73 @@ impl WiresharkPlugin {
 
         borrow.alloc_slice_fill_iter(iter).as_ptr() as *mut T
     }
+
+    // Allocates a single value in the global allocator and returns a pointer to it. The value is
+    // guarenteed to be valid for the lifetime of the plugin, but will never be dropped.
+    pub(crate) fn alloc_value<T>(&self, value: T) -> *mut T {
+        let alloc = self.global_alloc.clone();
+        let borrow = alloc.as_ref().borrow();
+
+        borrow.alloc(value) as *mut T
+    }
 }
 
 // Used to describe the translation between a u32 value and a string
 // to be displayed
 type WiresharkFieldValueString<'a> = (u32, &'a str);
 
-type WiresharkFieldValueStringOwned = (u32, String);
+// Used to describe the translation between a u64 value and a string to be displayed
+type WiresharkFieldValue64String<'a> = (u64, &'a str);
+
+// Used to describe the translation between an inclusive range of values and a string to be displayed
+type WiresharkFieldRangeString<'a> = (u64, u64, &'a str);
+
+// Used to describe the translation between a string value and a string to be displayed
+type WiresharkFieldStringString<'a> = (&'a str, &'a str);
+
+// A table of names for string values, like a string_string table in C. Wireshark does not display names next to
+// the value of a string field, so the dissector looks up the name of a value and adds it to the item itself.
+pub struct StringValueTable {
+    values: Vec<(String, String)>,
+}
+
+impl StringValueTable {
+    // Create a table from a list of (value, name) pairs
+    pub fn new(values: Vec<WiresharkFieldStringString<'_>>) -> Self {
+        Self {
+            values: values.into_iter().map(|(value, str)| (value.to_string(), str.to_string())).collect(),
+        }
+    }
+
+    // Get the name of a value, or None if the table has no name for it
+    pub fn get(&self, value: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(candidate, _)| candidate == value)
+            .map(|(_, name)| name.as_str())
+    }
+
+    // Get the name of a value, or the given text if the table has no name for it
+    pub fn get_or<'b>(&'b self, value: &str, unknown: &'b str) -> &'b str {
+        self.get(value).unwrap_or(unknown)
+    }
+}
+
+// Describes the strings used to display the value of a field
+enum WiresharkFieldStrings {
+    // A list of value names, see WiresharkFieldArgs::with_values
+    Values(Vec<(u32, String)>),
+    // A list of value names for 64 bit fields
+    Values64(Vec<(u64, String)>),
+    // A list of names for ranges of values
+    Ranges(Vec<(u64, u64, String)>),
+    // A list of value names that is searched with a binary search
+    ValuesExt(Vec<(u32, String)>),
+    // The labels shown for a true and false boolean value
+    TrueFalse(String, String),
+    // A Rust function that formats the value
+    Custom(WiresharkCustomFormat),
+}
 
 // Describes a field that is going to be registered with a protocol
 pub struct WiresharkFieldArgs<'a> {
@@ -153,9 +340,14 @@ pub struct WiresharkFieldArgs<'a> {
     // The way to display the field when rendered
     display: FieldDisplayType,
 
-    // A list of value names to display next to the field. The dissector will
-    // check all values in the vec and display the string value of one that matches.
-    str_values: Option<Vec<WiresharkFieldValueString<'a>>>,
+    // The strings to display next to the value of the field
+    strings: Option<WiresharkFieldStrings>,
+
+    // The bits of the value that belong to this field, or 0 if the field uses the whole value
+    bitmask: u64,
+
+    // The width of the whole value a boolean flag belongs to
+    boolean_width: Option<FieldBitWidth>,
 }
 
 impl<'a> WiresharkFieldArgs<'a> {
@@ -166,7 +358,9 @@ impl<'a> WiresharkFieldArgs<'a> {
             name: name,
             field_type: FieldType::None,
             display: FieldDisplayType::BaseNone,
-            str_values: None,
+            strings: None,
+            bitmask: 0,
+            boolean_width: None,
         }
     }
 
@@ -184,7 +378,94 @@ impl<'a> WiresharkFieldArgs<'a> {
     // Add a list of value names to display next to the field. The dissector will
     // check all values in the vec and display the string value of one that matches.
     pub fn with_values(mut self, values: Vec<WiresharkFieldValueString<'a>>) -> Self {
-        self.str_values = Some(values);
+        self.strings = Some(WiresharkFieldStrings::Values(
+            values.into_iter().map(|(value, str)| (value, str.to_string())).collect(),
+        ));
+        self
+    }
+
+    // Add a list of value names to display next to a signed field of up to 32 bits
+    pub fn with_signed_values(mut self, values: Vec<(i32, &'a str)>) -> Self {
+        self.strings = Some(WiresharkFieldStrings::Values(
+            values.into_iter().map(|(value, str)| (value as u32, str.to_string())).collect(),
+        ));
+        self
+    }
+
+    // Add a list of value names to display next to a 64 bit field
+    pub fn with_values64(mut self, values: Vec<WiresharkFieldValue64String<'a>>) -> Self {
+        self.strings = Some(WiresharkFieldStrings::Values64(
+            values.into_iter().map(|(value, str)| (value, str.to_string())).collect(),
+        ));
+        self
+    }
+
+    // Add a list of value names to display next to a signed 64 bit field
+    pub fn with_signed_values64(mut self, values: Vec<(i64, &'a str)>) -> Self {
+        self.strings = Some(WiresharkFieldStrings::Values64(
+            values.into_iter().map(|(value, str)| (value as u64, str.to_string())).collect(),
+        ));
+        self
+    }
+
+    // Add a list of inclusive (min, max) ranges of values and the name to display for values within each range,
+    // such as (0x1000, 0x1FFF, "Vendor specific")
+    pub fn with_ranges(mut self, ranges: Vec<WiresharkFieldRangeString<'a>>) -> Self {
+        self.strings = Some(WiresharkFieldStrings::Ranges(
+            ranges.into_iter().map(|(min, max, str)| (min, max, str.to_string())).collect(),
+        ));
+        self
+    }
+
+    // Add a large list of value names, such as a registry of message types. The values are sorted during
+    // registration so they can be looked up with a binary search.
+    pub fn with_values_ext(mut self, values: Vec<WiresharkFieldValueString<'a>>) -> Self {
+        let mut values: Vec<(u32, String)> =
+            values.into_iter().map(|(value, str)| (value, str.to_string())).collect();
+        values.sort_by_key(|(value, _)| *value);
+
+        self.strings = Some(WiresharkFieldStrings::ValuesExt(values));
+        self
+    }
+
+    // Add the labels to display for a boolean field instead of "True" and "False"
+    pub fn with_true_false(mut self, true_string: &'a str, false_string: &'a str) -> Self {
+        self.strings = Some(WiresharkFieldStrings::TrueFalse(
+            true_string.to_string(),
+            false_string.to_string(),
+        ));
+        self
+    }
+
+    // Format the value of an integer field with the given function, which returns the text to display. The field
+    // can still be filtered on its raw value. Signed values are passed as their two's complement bit pattern, so
+    // they should be cast back to the signed type of the field, such as value as u32 as i32 for an Int32 field.
+    pub fn with_custom_format<F: Fn(u64) -> String + 'static>(mut self, format_fn: F) -> Self {
+        self.display = FieldDisplayType::BaseCustom;
+        self.strings = Some(WiresharkFieldStrings::Custom(WiresharkCustomFormat::Integer(Box::new(format_fn))));
+        self
+    }
+
+    // Format the value of a float or double field with the given function, which returns the text to display
+    pub fn with_custom_float_format<F: Fn(f64) -> String + 'static>(mut self, format_fn: F) -> Self {
+        self.display = FieldDisplayType::BaseCustom;
+        self.strings = Some(WiresharkFieldStrings::Custom(WiresharkCustomFormat::Float(Box::new(format_fn))));
+        self
+    }
+
+    // Only display the given bits of the value, shifted down to the lowest set bit of the mask. Used for
+    // flags and bit ranges within a larger value, see DissectorSubTree::add_bitmask. Boolean fields with a
+    // bitmask are shown within the width of the whole value, see with_boolean_width.
+    pub fn with_bitmask(mut self, bitmask: u64) -> Self {
+        self.bitmask = bitmask;
+        self
+    }
+
+    // Set the width of the whole value a boolean field with a bitmask is a flag of, such as FieldBitWidth::Bits16
+    // for a flag within a u16 header. This replaces the display type of the field. When it is not set, the width
+    // is the smallest number of whole bytes that holds the bitmask.
+    pub fn with_boolean_width(mut self, width: FieldBitWidth) -> Self {
+        self.boolean_width = Some(width);
         self
     }
 }
@@ -194,7 +475,9 @@ struct WiresharkFieldDefinition {
     name: String,
     field_type: FieldType,
     display: FieldDisplayType,
-    str_values: Option<Vec<WiresharkFieldValueStringOwned>>,
+    strings: Option<WiresharkFieldStrings>,
+    bitmask: u64,
+    boolean_width: Option<FieldBitWidth>,
 }
 
 // Describes a protocol that is going to be registered with Wireshark
@@ -202,43 +485,78 @@ pub struct WiresharkProtocolDefinition {
     name: String,
     id: String,
     filter: String,
-    dissector_fn: DissectorCallback,
+    dissector: Box<dyn Dissector>,
 
     fields: Vec<WiresharkFieldDefinition>,
     match_definitions: Vec<WiresharkMatchDefinition>,
+    heuristic_definitions: Vec<WiresharkHeuristicDefinition>,
+    tcp_framing: Option<WiresharkTcpFramingDefinition>,
+    reassembly_tables: Vec<WiresharkReassemblyDefinition>,
+    expert_infos: Vec<WiresharkExpertDefinition>,
+    preferences: Vec<WiresharkPreferenceDefinition>,
+    preferences_fn: Option<PreferencesCallback>,
+    dissector_tables: Vec<WiresharkDissectorTableDefinition>,
+    called_dissectors: Vec<String>,
+    conversation_table: Option<WiresharkConversationTable>,
+    export_objects: bool,
+    follow_filter_template: Option<String>,
+    postdissector_fields: Option<Vec<String>>,
+    extracted_fields: Vec<String>,
     num_ett_fields: usize,
 }
 
 impl WiresharkProtocolDefinition {
     // Create a new protocol definition
     pub fn new(dissector_fn: DissectorCallback, name: &str, id: &str, filter: &str) -> Self {
+        Self::from_dissector(CallbackDissector(dissector_fn), name, id, filter)
+    }
+
+    // Create a new protocol definition that is dissected by the given closure, which can capture the
+    // configuration of the protocol
+    pub fn from_closure<F>(dissector_fn: F, name: &str, id: &str, filter: &str) -> Self
+    where
+        F: Fn(DissectorSubTree<'_>) -> DissectResult + 'static,
+    {
+        Self::from_dissector(dissector_fn, name, id, filter)
+    }
+
+    // Create a new protocol definition that is dissected by the given dissector. The dissector is kept for the
+    // lifetime of the plugin, and the same type can be used for several protocols with different parameters.
+    pub fn from_dissector<D: Dissector>(dissector: D, name: &str, id: &str, filter: &str) -> Self {
         Self {
             name: name.to_string(),
             id: id.to_string(),
             filter: filter.to_string(),
-            dissector_fn: dissector_fn,
+            dissector: Box::new(dissector),
             fields: Vec::new(),
             match_definitions: Vec::new(),
+            heuristic_definitions: Vec::new(),
+            tcp_framing: None,
+            reassembly_tables: Vec::new(),
+            expert_infos: Vec::new(),
+            preferences: Vec::new(),
+            preferences_fn: None,
+            dissector_tables: Vec::new(),
+            called_dissectors: Vec::new(),
+            conversation_table: None,
+            export_objects: false,
+            follow_filter_template: None,
+            postdissector_fields: None,
+            extracted_fields: Vec::new(),
             num_ett_fields: 1,
         }
     }
 
     // Add a field definition to the protocol
     pub fn add_field_type<'a>(&mut self, definition: WiresharkFieldArgs<'a>) {
-        // If there is any str_values applied, convert them to WiresharkFieldValueStringOwned
-        let str_values = definition.str_values.map(|values| {
-            values
-                .into_iter()
-                .map(|(value, str)| (value, str.to_string()))
-                .collect()
-        });
-
         self.fields.push(WiresharkFieldDefinition {
             id: definition.id.to_string(),
             name: definition.name.to_string(),
             field_type: definition.field_type,
             display: definition.display,
-            str_values: str_values,
+            strings: definition.strings,
+            bitmask: definition.bitmask,
+            boolean_width: definition.boolean_width,
         });
     }
 
@@ -250,6 +568,209 @@ impl WiresharkProtocolDefinition {
         });
     }
 
+    // Add a heuristic condition by which the dissector will be run for a packet. The heuristic function is
+    // offered packets from the given heuristic table (such as "udp" or "tcp") and returns true if the packet
+    // belongs to this protocol, in which case the packet is passed on to the dissector function. The function can
+    // be a closure capturing the configuration of the protocol.
+    // The display name is shown in the Enabled Protocols dialog, and the internal name must be globally unique.
+    pub fn add_heuristic_condition<F>(
+        &mut self,
+        table: &str,
+        display_name: &str,
+        internal_name: &str,
+        heuristic_fn: F,
+        enabled: bool,
+    ) where
+        F: Fn(TvBuff<'_>) -> bool + 'static,
+    {
+        if self.heuristic_definitions.iter().any(|def| def.table == table) {
+            panic!(
+                "Heuristic table {} already registered for protocol {}, only one heuristic per table is allowed.",
+                table, self.id
+            );
+        }
+
+        self.heuristic_definitions.push(WiresharkHeuristicDefinition {
+            table: table.to_string(),
+            display_name: display_name.to_string(),
+            internal_name: internal_name.to_string(),
+            heuristic_fn: Box::new(heuristic_fn),
+            enabled: enabled,
+        });
+    }
+
+    // Split the TCP stream into PDUs before they are dissected. Each PDU begins with a header of fixed_len bytes,
+    // and the PDU length function is given the data at the start of the PDU once that many bytes are available
+    // and returns the total length of the PDU including the header. The dissector function is then called once
+    // for each complete PDU, reassembling PDUs that span multiple TCP segments. Reassembly can be disabled
+    // through the protocol's "desegment" preference. Framing is only applied to packets that arrive over TCP, and
+    // calling this again replaces the previous framing. Like the dissector, the PDU length function can be a
+    // closure capturing the configuration of the protocol.
+    pub fn set_tcp_pdu_framing<F>(&mut self, fixed_len: u32, pdu_len_fn: F)
+    where
+        F: Fn(TvBuff<'_>) -> u32 + 'static,
+    {
+        let replaced = self.tcp_framing.replace(WiresharkTcpFramingDefinition {
+            fixed_len: fixed_len,
+            pdu_len_fn: Box::new(pdu_len_fn),
+        });
+
+        // The desegment preference was already added by the first call
+        if replaced.is_some() {
+            return;
+        }
+
+        self.add_preference(
+            WiresharkPreferenceArgs::new("desegment", "Reassemble messages spanning multiple TCP segments")
+                .with_description(
+                    "Whether the dissector should reassemble messages spanning multiple TCP segments. \
+                     To use this option, you must also enable \"Allow subdissectors to reassemble TCP streams\" \
+                     in the TCP protocol settings.",
+                )
+                .with_bool(true),
+        );
+    }
+
+    // Add a preference to the protocol, which can be changed by the user in the preferences dialog
+    // and read during dissection through DissectorSubTree::preferences
+    pub fn add_preference<'a>(&mut self, definition: WiresharkPreferenceArgs<'a>) {
+        let definition = definition.into_definition();
+        if self.preferences.iter().any(|pref| pref.name == definition.name) {
+            panic!("Preference {} already registered, choose a different name.", definition.name);
+        }
+
+        self.preferences.push(definition);
+    }
+
+    // Set a function that is called after the user changes the protocol's preferences
+    pub fn set_preferences_callback(&mut self, preferences_fn: PreferencesCallback) {
+        self.preferences_fn = Some(preferences_fn);
+    }
+
+    // Add a table used to reassemble messages that are split into multiple fragments. The ID is used to refer to
+    // the table during dissection and as the filter prefix of the fragment fields that are added to the tree,
+    // and the name describes the reassembled message, such as "Message".
+    pub fn add_reassembly_table(&mut self, id: &str, name: &str) {
+        if self.reassembly_tables.iter().any(|table| table.id == id) {
+            panic!("Reassembly table {} already registered, choose a different ID.", id);
+        }
+
+        // Add all of the fields Wireshark uses to describe the fragments of a message
+        let fields = [
+            ("fragments", "fragments", FieldType::None, FieldDisplayType::BaseNone),
+            ("fragment", "fragment", FieldType::Framenum, FieldDisplayType::BaseNone),
+            ("fragment.overlap", "fragment overlap", FieldType::Boolean, FieldDisplayType::BaseNone),
+            ("fragment.overlap.conflicts", "fragment overlapping with conflicting data", FieldType::Boolean, FieldDisplayType::BaseNone),
+            ("fragment.multiple_tails", "has multiple tail fragments", FieldType::Boolean, FieldDisplayType::BaseNone),
+            ("fragment.too_long_fragment", "fragment too long", FieldType::Boolean, FieldDisplayType::BaseNone),
+            ("fragment.error", "defragmentation error", FieldType::Framenum, FieldDisplayType::BaseNone),
+            ("fragment.count", "fragment count", FieldType::Uint32, FieldDisplayType::BaseDec),
+            ("reassembled.in", "reassembled in", FieldType::Framenum, FieldDisplayType::BaseNone),
+            ("reassembled.length", "reassembled length", FieldType::Uint32, FieldDisplayType::BaseDec),
+            ("reassembled.data", "reassembled data", FieldType::Bytes, FieldDisplayType::BaseNone),
+        ];
+
+        for (suffix, description, field_type, display) in fields {
+            let field_id = format!("{}.{}", id, suffix);
+            let field_name = format!("{} {}", name, description);
+
+            self.add_field_type(
+                WiresharkFieldArgs::new(&field_id, &field_name)
+                    .with_field_type(field_type)
+                    .with_display(display),
+            );
+        }
+
+        self.reassembly_tables.push(WiresharkReassemblyDefinition {
+            id: id.to_string(),
+            name: name.to_string(),
+        });
+    }
+
+    // Add an expert info to the protocol, which can be attached to items during dissection to report problems
+    // in the Expert Information dialog. The ID is the filter name of the expert info, such as "test.bad_length".
+    pub fn add_expert_info(&mut self, id: &str, group: ExpertGroup, severity: ExpertSeverity, summary: &str) {
+        if self.expert_infos.iter().any(|expert| expert.id == id) {
+            panic!("Expert info {} already registered, choose a different ID.", id);
+        }
+
+        self.expert_infos.push(WiresharkExpertDefinition {
+            id: id.to_string(),
+            group: group,
+            severity: severity,
+            summary: summary.to_string(),
+        });
+    }
+
+    // Add a dissector table owned by this protocol, which other dissectors can register to with a match condition
+    // on the table's ID. The key type is one of the unsigned integer field types, displayed with the given display
+    // type in the Decode As dialog, or FieldType::String. The UI name is shown in the Decode As dialog.
+    pub fn add_dissector_table(&mut self, id: &str, ui_name: &str, key_type: FieldType, display: FieldDisplayType) {
+        match key_type {
+            FieldType::Uint8 | FieldType::Uint16 | FieldType::Uint24 | FieldType::Uint32 | FieldType::String => {}
+            _ => panic!("Dissector table {} must be keyed by an unsigned integer or a string.", id),
+        }
+
+        if self.dissector_tables.iter().any(|table| table.id == id) {
+            panic!("Dissector table {} already registered, choose a different ID.", id);
+        }
+
+        self.dissector_tables.push(WiresharkDissectorTableDefinition {
+            id: id.to_string(),
+            ui_name: ui_name.to_string(),
+            key_type: key_type,
+            display: display,
+        });
+    }
+
+    // Declare a dissector registered with Wireshark, such as "json", "eth_withoutfcs" or "ip", that will be called
+    // on payloads during dissection. The dissector is looked up by name once during handoff.
+    pub fn add_called_dissector(&mut self, name: &str) {
+        if !self.called_dissectors.iter().any(|called| called == name) {
+            self.called_dissectors.push(name.to_string());
+        }
+    }
+
+    // List the protocol in the Conversations and Endpoints dialogs. The endpoints function is called with every
+    // record of type T that the protocol queues to its tap with DissectorSubTree::queue_tap, and picks the
+    // endpoints of the packet. Packets without a record are not counted.
+    pub fn set_conversation_table<'a, T: 'static>(
+        &mut self,
+        args: WiresharkConversationTableArgs<'a>,
+        endpoints_fn: ConversationCallback<T>,
+    ) {
+        self.conversation_table = Some(WiresharkConversationTable::new(args, endpoints_fn));
+    }
+
+    // List the protocol in File -> Export Objects and tshark --export-objects, so files published during dissection
+    // with DissectorSubTree::export_object can be saved
+    pub fn enable_export_objects(&mut self) {
+        self.export_objects = true;
+    }
+
+    // Add the protocol to the Follow Stream menu and tshark -z follow, showing the payloads emitted during
+    // dissection with DissectorSubTree::follow_stream_payload. The filter template selects the packets of one
+    // stream, with {} replaced by the stream index, such as "myproto.stream == {}".
+    pub fn set_follow_stream(&mut self, filter_template: &str) {
+        self.follow_filter_template = Some(filter_template.to_string());
+    }
+
+    // Run the protocol's dissector function on every frame after all other dissectors, instead of matching it
+    // against packets. The wanted fields are abbreviations of fields of any protocol, such as "ip.ttl", which are
+    // made available to DissectorSubTree::get_field_values even when the packet tree is not being built.
+    pub fn set_postdissector(&mut self, wanted_fields: &[&str]) {
+        self.postdissector_fields = Some(wanted_fields.iter().map(|field| field.to_string()).collect());
+    }
+
+    // Declare a field of any protocol, such as "tcp.stream" or "frame.time_epoch", that the dissector function
+    // reads with DissectorSubTree::get_field_values. The field is looked up by its abbreviation once during handoff.
+    // If no protocol registered it, a warning is logged and the field never has any values.
+    pub fn add_extracted_field(&mut self, abbrev: &str) {
+        if !self.extracted_fields.iter().any(|field| field == abbrev) {
+            self.extracted_fields.push(abbrev.to_string());
+        }
+    }
+
     // Set the number of fields in the ETT for this protocol
     pub fn set_num_ett(&mut self, num_ett: usize) {
         if num_ett < 1 {
@@ -272,9 +793,108 @@ pub enum WiresharkMatchType {
     String(String),
 }
 
+// Describes a heuristic condition for a dissector
+struct WiresharkHeuristicDefinition {
+    // The heuristic table to register to, such as "udp" or "tcp"
+    table: String,
+
+    // The name shown for the heuristic in the Enabled Protocols dialog
+    display_name: String,
+
+    // The globally unique name of the heuristic
+    internal_name: String,
+
+    // Function called to decide if a packet belongs to this protocol
+    heuristic_fn: HeuristicCallback,
+
+    // True if the heuristic is enabled by default
+    enabled: bool,
+}
+
+// Describes how a TCP stream is split into PDUs for a dissector
+struct WiresharkTcpFramingDefinition {
+    // The number of bytes needed to determine the length of a PDU
+    fixed_len: u32,
+
+    // Function called to determine the total length of a PDU
+    pdu_len_fn: PduLengthCallback,
+}
+
+// Describes a dissector table that is going to be registered with a protocol
+struct WiresharkDissectorTableDefinition {
+    id: String,
+    ui_name: String,
+    key_type: FieldType,
+    display: FieldDisplayType,
+}
+
+struct DissectorTableHandle {
+    id: String,
+    table: dissector_table_t,
+}
+
+struct CalledDissectorHandle {
+    name: String,
+
+    // Handle found during handoff, or null if no dissector is registered with the name
+    handle: *mut dissector_handle,
+}
+
+// Describes an expert info that is going to be registered with a protocol
+struct WiresharkExpertDefinition {
+    id: String,
+    group: ExpertGroup,
+    severity: ExpertSeverity,
+    summary: String,
+}
+
+struct ExpertHandle {
+    id: String,
+
+    // The expert field filled in by Wireshark, allocated in the global allocator
+    field: *mut expert_field,
+}
+
+// Describes a table used to reassemble fragmented messages
+struct WiresharkReassemblyDefinition {
+    // The ID of the table, which is also the prefix of its fragment fields
+    id: String,
+
+    // The name of the reassembled message
+    name: String,
+}
+
+// A table used to reassemble fragmented messages for a registered protocol
+struct ReassemblyTable {
+    // The ID of the table
+    id: String,
+
+    // The name of the data source that holds the reassembled message
+    data_source_name: *const c_char,
+
+    // The Wireshark reassembly table, allocated in the global allocator
+    table: *mut reassembly_table,
+
+    // The fields and subtrees used to display fragments, allocated in the global allocator
+    items: *mut fragment_items,
+}
+
+// Describes how a TCP stream is split into PDUs for a registered protocol
+struct TcpFraming {
+    // The number of bytes needed to determine the length of a PDU
+    fixed_len: u32,
+
+    // Function called to determine the total length of a PDU
+    pdu_len_fn: PduLengthCallback,
+
+    // Value of the desegment preference, registered to Wireshark
+    desegment: *mut bool,
+}
+
 struct FieldHandle {
     handle: c_int,
     id: String,
+    field_type: FieldType,
     _ptr: *mut hf_register_info,
 }
 
@@ -282,6 +902,9 @@ pub struct WiresharkProtocol {
     // Protocol handle
     proto_handle: c_int,
 
+    // Handle of the tap named after the protocol filter, which records are queued to with DissectorSubTree::queue_tap
+    tap_id: c_int,
+
     // Holds the collapse state of the subtree
     ett_handles: Vec<c_int>,
 
@@ -291,8 +914,8 @@ pub struct WiresharkProtocol {
     // Unique ID of this protocol
     id: *const i8,
 
-    // Function called to handle protocol dissection
-    pub(crate) dissector_fn: DissectorCallback,
+    // Called to handle protocol dissection
+    dissector: Box<dyn Dissector>,
 
     // All registered fields for this protocol
     fields: Vec<FieldHandle>,
@@ -300,13 +923,115 @@ pub struct WiresharkProtocol {
     // Pending match conditions for this protocol that have not yet been registered
     match_definitions: Option<Vec<WiresharkMatchDefinition>>,
 
+    // Heuristic conditions for this protocol, kept to look up the heuristic function during dissection
+    heuristic_definitions: Vec<WiresharkHeuristicDefinition>,
+
+    // If set, TCP streams are split into PDUs before they are passed to the dissector function
+    tcp_framing: Option<TcpFraming>,
+
+    // The preferences module of this protocol, or null if it has not yet been registered
+    prefs_module: *mut module_t,
+
+    // All registered preferences for this protocol
+    preferences: Vec<PreferenceHandle>,
+
+    // Function called after the protocol's preferences have been changed
+    preferences_fn: Option<PreferencesCallback>,
+
+    // Ranges registered to dissector tables by Preferences::update_match_range, keyed by table name
+    pub(crate) applied_ranges: RefCell<HashMap<String, *mut range_t>>,
+
+    // Handle to the protocol's dissector, or null if it has not yet been created during handoff
+    dissector_handle: *mut dissector_handle,
+
+    // All tables used to reassemble fragmented messages for this protocol
+    reassembly_tables: Vec<ReassemblyTable>,
+
+    // All registered expert infos for this protocol
+    experts: Vec<ExpertHandle>,
+
+    // All dissector tables owned by this protocol
+    dissector_tables: Vec<DissectorTableHandle>,
+
+    // Dissectors that are called on payloads by this protocol. The names are pending until handoff looks them up.
+    called_dissector_names: Option<Vec<String>>,
+    called_dissectors: Vec<CalledDissectorHandle>,
+
+    // The Conversations and Endpoints dialog support for this protocol, if any
+    pub(crate) conversation_table: Option<WiresharkConversationTable>,
+
+    // The filter selecting the packets of a followed stream, if the protocol supports Follow Stream
+    follow_filter_template: Option<String>,
+
+    // The number of streams seen by Follow Stream in the current capture file, which is one more than the highest
+    // stream index emitted
+    follow_stream_count: Cell<u32>,
+
+    // The stream currently being followed, whose payloads are passed to the Follow Stream dialog
+    followed_stream: Cell<Option<u32>>,
+
+    // The fields a postdissector wants Wireshark to fill in, or None if the protocol is not a postdissector
+    postdissector_fields: Option<Vec<String>>,
+
+    // Fields of any protocol read by the dissector. The names are pending until handoff looks them up.
+    extracted_field_names: Option<Vec<String>>,
+    extracted_fields: HashMap<String, c_int>,
+
     // A buffer that is used to format a rust string into a null terminated string before it is copied into an API function 
     nullterm_buffer: RefCell<Vec<u8>>,
 }
 
 // This callback is called when a packet is dissected for this protocol. It is passed a subtree
-// where items can be added.
-type DissectorCallback = unsafe fn(DissectorSubTree<'_>) -> ();
+// where items can be added, and returns how much of the packet belonged to this protocol.
+type DissectorCallback = unsafe fn(DissectorSubTree<'_>) -> DissectResult;
+
+// Dissects the packets of a protocol. Unlike a DissectorCallback, a dissector can hold state such as the
+// configuration of the protocol, which lets one type handle several variants of a protocol. Closures taking a
+// DissectorSubTree and returning a DissectResult are dissectors as well.
+pub trait Dissector: 'static {
+    // Called when a packet is dissected for this protocol. It is passed a subtree where items can be added, and
+    // returns how much of the packet belonged to this protocol.
+    fn dissect(&self, tree: DissectorSubTree<'_>) -> DissectResult;
+}
+
+impl<F> Dissector for F
+where
+    F: Fn(DissectorSubTree<'_>) -> DissectResult + 'static,
+{
+    fn dissect(&self, tree: DissectorSubTree<'_>) -> DissectResult {
+        self(tree)
+    }
+}
+
+// Adapts a DissectorCallback to the Dissector trait
+struct CallbackDissector(DissectorCallback);
+
+impl Dissector for CallbackDissector {
+    fn dissect(&self, tree: DissectorSubTree<'_>) -> DissectResult {
+        unsafe { (self.0)(tree) }
+    }
+}
+
+// The result of a dissector function, used to tell Wireshark how much of the packet was dissected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DissectResult {
+    // The dissector consumed the given number of bytes from the start of the packet, up to the captured length
+    Consumed(u32),
+    // The packet does not belong to this protocol and Wireshark should try the next dissector. The items the
+    // dissector added to the tree and its changes to the protocol and info columns are removed.
+    NotMine,
+    // The packet is incomplete and needs the given number of additional bytes before it can be dissected,
+    // or 0 if the amount is not yet known. Used by protocols over TCP to request desegmentation. When the lower
+    // layer can't desegment, the packet is kept as dissected so far.
+    NeedMoreData(u32),
+}
+
+// This callback is called with the data at the start of a TCP PDU and returns the total length of the PDU.
+type PduLengthCallback = Box<dyn Fn(TvBuff<'_>) -> u32>;
+
+// This callback is called when a packet in a heuristic table is offered to this protocol. It is passed
+// the packet data and returns true if the packet belongs to this protocol.
+type HeuristicCallback = Box<dyn Fn(TvBuff<'_>) -> bool>;
 
 impl WiresharkProtocol {
     // Register a new protocol with the given name, ID, and filter
@@ -314,7 +1039,7 @@ impl WiresharkProtocol {
     unsafe fn new(
         plugin: RefMut<'_, WiresharkPlugin>,
         match_definitions: Vec<WiresharkMatchDefinition>,
-        dissector_fn: DissectorCallback,
+        dissector: Box<dyn Dissector>,
         name: &str,
         id: &str,
         filter: &str,
@@ -322,11 +1047,31 @@ impl WiresharkProtocol {
     ) -> WiresharkProtocolRegistration {
         let mut obj = WiresharkProtocol {
             proto_handle: -1,
+            tap_id: -1,
             ett_handles: Vec::new(),
             fields: Vec::new(),
             id: null(),
-            dissector_fn: dissector_fn,
+            dissector: dissector,
             match_definitions: Some(match_definitions),
+            heuristic_definitions: Vec::new(),
+            tcp_framing: None,
+            prefs_module: null_mut(),
+            preferences: Vec::new(),
+            preferences_fn: None,
+            applied_ranges: RefCell::new(HashMap::new()),
+            dissector_handle: null_mut(),
+            reassembly_tables: Vec::new(),
+            experts: Vec::new(),
+            dissector_tables: Vec::new(),
+            called_dissector_names: None,
+            called_dissectors: Vec::new(),
+            conversation_table: None,
+            follow_filter_template: None,
+            follow_stream_count: Cell::new(0),
+            followed_stream: Cell::new(None),
+            postdissector_fields: None,
+            extracted_field_names: None,
+            extracted_fields: HashMap::new(),
             ett_handles_ptrs: Vec::with_capacity(num_ett),
             nullterm_buffer: RefCell::new(Vec::with_capacity(1024))
         };
@@ -335,13 +1080,16 @@ impl WiresharkProtocol {
         obj.id = id_str;
 
         // Register the protocol itself
-        obj.proto_handle = proto_register_protocol(
-            plugin.alloc_string(name),
-            id_str,
-            plugin.alloc_string(filter),
-        );
+        let filter_str = plugin.alloc_string(filter);
+        obj.proto_handle = proto_register_protocol(plugin.alloc_string(name), id_str, filter_str);
         assert!(obj.proto_handle >= 0);
 
+        // Register a tap with the same name as the protocol's filter, so listeners can receive its records. The
+        // Conversations, Export Objects and Follow Stream dialogs look up the tap of a protocol by its filter name,
+        // so the tap can't be named after the protocol ID.
+        obj.tap_id = register_tap(filter_str);
+        mark_plugshark_tap(filter);
+
         obj.ett_handles.resize(num_ett, -1);
 
         // Create a new vector that, for each ett_handles entry, has a pointer to the corresponding entry
@@ -358,38 +1106,82 @@ impl WiresharkProtocol {
     }
 
     // Add a field to the protocol
-    unsafe fn register_field(
-        &mut self,
-        plugin: RefMut<'_, WiresharkPlugin>,
-        name: &str,
-        abbrev: &str,
-        type_: FieldType,
-        values: &Option<Vec<WiresharkFieldValueStringOwned>>,
-        display: FieldDisplayType,
-    ) {
-        let mut values_ptr: *const _value_string = null();
+    unsafe fn register_field(&mut self, mut plugin: RefMut<'_, WiresharkPlugin>, field: WiresharkFieldDefinition) {
+        let mut strings_ptr: *const c_void = null();
 
-        // Determine if we should allocate a values string array
-        if let Some(values) = values {
-            let values_str = values.iter().map(|value| _value_string {
-                value: value.0,
-                strptr: plugin.alloc_string(value.1.as_str()),
-            });
+        // Some kinds of strings tables need a flag in the display type to tell Wireshark how to read them
+        let mut display_flags: c_int = 0;
+
+        // Boolean flags are displayed within the width of the whole value they belong to
+        let display = match (field.field_type, field.boolean_width) {
+            (FieldType::Boolean, Some(width)) => width.to_i32(),
+            (FieldType::Boolean, None) if field.bitmask != 0 => FieldBitWidth::from_bitmask(field.bitmask).to_i32(),
+            _ => field.display.to_i32(),
+        };
 
-            // Allocate the value strings table as a flat C array
-            values_ptr = plugin.alloc_flat_c_array_iter(values_str);
+        // Determine if we should allocate a strings table. Each table is allocated as a flat C array that
+        // is terminated by an entry with a null string.
+        match field.strings {
+            Some(WiresharkFieldStrings::Values(values)) => {
+                strings_ptr = Self::alloc_value_strings(&plugin, &values) as *const c_void;
+            }
+            Some(WiresharkFieldStrings::Values64(values)) => {
+                let values_str = values
+                    .iter()
+                    .map(|value| _val64_string {
+                        value: value.0,
+                        strptr: plugin.alloc_string(value.1.as_str()),
+                    })
+                    .chain(std::iter::once(_val64_string { value: 0, strptr: null() }));
+
+                strings_ptr = plugin.alloc_flat_c_array_iter(values_str.collect::<Vec<_>>()) as *const c_void;
+                display_flags = BASE_VAL64_STRING as c_int;
+            }
+            Some(WiresharkFieldStrings::Ranges(ranges)) => {
+                let ranges_str = ranges
+                    .iter()
+                    .map(|range| _range_string {
+                        value_min: range.0,
+                        value_max: range.1,
+                        strptr: plugin.alloc_string(range.2.as_str()),
+                    })
+                    .chain(std::iter::once(_range_string { value_min: 0, value_max: 0, strptr: null() }));
+
+                strings_ptr = plugin.alloc_flat_c_array_iter(ranges_str.collect::<Vec<_>>()) as *const c_void;
+                display_flags = BASE_RANGE_STRING as c_int;
+            }
+            Some(WiresharkFieldStrings::ValuesExt(values)) => {
+                // The extended table wraps the regular value strings table, and counts the terminating entry
+                let values_ptr = Self::alloc_value_strings(&plugin, &values);
+                strings_ptr = value_string_ext_new(
+                    values_ptr,
+                    values.len() as c_uint + 1,
+                    plugin.alloc_string(&field.id),
+                ) as *const c_void;
+                display_flags = BASE_EXT_STRING as c_int;
+            }
+            Some(WiresharkFieldStrings::TrueFalse(true_string, false_string)) => {
+                strings_ptr = plugin.alloc_value(true_false_string {
+                    true_string: plugin.alloc_string(&true_string),
+                    false_string: plugin.alloc_string(&false_string),
+                }) as *const c_void;
+            }
+            Some(WiresharkFieldStrings::Custom(format)) => {
+                strings_ptr = register_custom_format(&mut plugin, &field.id, field.field_type, format);
+            }
+            None => {}
         }
 
         let mut out_handle: c_int = -1;
         let obj = hf_register_info {
             p_id: &mut out_handle,
             hfinfo: header_field_info {
-                name: plugin.alloc_string(name),
-                abbrev: plugin.alloc_string(abbrev),
-                type_: type_.to_u32(),
-                display: display.to_i32(),
-                strings: values_ptr as *const c_void,
-                bitmask: 0x0,
+                name: plugin.alloc_string(&field.name),
+                abbrev: plugin.alloc_string(&field.id),
+                type_: field.field_type.to_u32(),
+                display: display | display_flags,
+                strings: strings_ptr,
+                bitmask: field.bitmask,
                 blurb: null(),
                 id: -1,
                 parent: 0,
@@ -411,13 +1203,320 @@ impl WiresharkProtocol {
         self.fields.push(
             FieldHandle {
                 handle: out_handle,
-                id: abbrev.to_string(),
+                id: field.id.clone(),
+                field_type: field.field_type,
                 _ptr: hf,
             },
         );
     }
 
-    // Get the handle to the protocol's ETT
+    // Allocate a value strings table as a flat C array terminated by an entry with a null string
+    fn alloc_value_strings(plugin: &WiresharkPlugin, values: &[(u32, String)]) -> *const _value_string {
+        let values_str = values
+            .iter()
+            .map(|value| _value_string {
+                value: value.0,
+                strptr: plugin.alloc_string(value.1.as_str()),
+            })
+            .chain(std::iter::once(_value_string { value: 0, strptr: null() }));
+
+        plugin.alloc_flat_c_array_iter(values_str.collect::<Vec<_>>())
+    }
+
+    // Get the preferences module of the protocol, registering it on first use. Wireshark does not pass any context
+    // to the function called when a module's preferences are applied, so each module gets its own.
+    unsafe fn get_prefs_module(&mut self, plugin: &mut WiresharkPlugin) -> *mut module_t {
+        if self.prefs_module.is_null() {
+            let proto_id = CStr::from_ptr(self.id).to_string_lossy().into_owned();
+            let slot = claim_slot(
+                plugin.preference_modules.len(),
+                &format!("Protocol {}", proto_id),
+                "protocols with preferences",
+            );
+
+            plugin.preference_modules.push(proto_id);
+            self.prefs_module = prefs_register_protocol(self.proto_handle, PREFS_APPLY_DISPATCHERS[slot]);
+        }
+
+        self.prefs_module
+    }
+
+    // Register all preferences of the protocol and the function called when they change
+    unsafe fn register_preferences(
+        &mut self,
+        mut plugin: RefMut<'_, WiresharkPlugin>,
+        definitions: Vec<WiresharkPreferenceDefinition>,
+        preferences_fn: Option<PreferencesCallback>,
+    ) {
+        if definitions.is_empty() && preferences_fn.is_none() {
+            return;
+        }
+
+        let module = self.get_prefs_module(&mut plugin);
+        for definition in definitions {
+            self.preferences.push(definition.register(&plugin, module));
+        }
+
+        self.preferences_fn = preferences_fn;
+    }
+
+    // Get a preference that has already been registered
+    pub(crate) fn get_preference(&self, name: &str) -> &PreferenceHandle {
+        self.preferences
+            .iter()
+            .find(|pref| pref.name == name)
+            .expect(format!("Preference {} not registered in protocol.", name).as_str())
+    }
+
+    // Get the handle to the protocol's dissector, or null if handoff has not happened yet
+    pub(crate) fn get_dissector_handle(&self) -> *mut dissector_handle {
+        self.dissector_handle
+    }
+
+    // Split TCP streams into PDUs before they are dissected. The desegment preference must already be registered.
+    unsafe fn register_tcp_framing(&mut self, framing: WiresharkTcpFramingDefinition) {
+        let desegment = match self.get_preference("desegment").storage {
+            PreferenceStorage::Bool(var) => var,
+            _ => panic!("Preference desegment is not a bool preference."),
+        };
+
+        self.tcp_framing = Some(TcpFraming {
+            fixed_len: framing.fixed_len,
+            pdu_len_fn: framing.pdu_len_fn,
+            desegment: desegment,
+        });
+    }
+
+    // Register a table used to reassemble fragmented messages. The fragment fields of the table must already be
+    // registered to the protocol.
+    unsafe fn register_reassembly_table(
+        &mut self,
+        plugin: RefMut<'_, WiresharkPlugin>,
+        definition: WiresharkReassemblyDefinition,
+    ) {
+        // The fragment items reference the field handles by pointer, so copy them somewhere that lives as long as the plugin
+        let field_ptr = |suffix: &str| -> *mut c_int {
+            let abbrev = format!("{}.{}", definition.id, suffix);
+            plugin.alloc_value(self.get_field_handle(&abbrev).handle)
+        };
+
+        // Register the subtrees for the fragment list and each fragment
+        let ett_fragment = plugin.alloc_value(-1 as c_int);
+        let ett_fragments = plugin.alloc_value(-1 as c_int);
+        let ett_ptrs = plugin.alloc_flat_c_array_iter([ett_fragment, ett_fragments]);
+        proto_register_subtree_array(ett_ptrs, 2);
+
+        let mut items: fragment_items = std::mem::zeroed();
+        items.ett_fragment = ett_fragment;
+        items.ett_fragments = ett_fragments;
+        items.hf_fragments = field_ptr("fragments");
+        items.hf_fragment = field_ptr("fragment");
+        items.hf_fragment_overlap = field_ptr("fragment.overlap");
+        items.hf_fragment_overlap_conflict = field_ptr("fragment.overlap.conflicts");
+        items.hf_fragment_multiple_tails = field_ptr("fragment.multiple_tails");
+        items.hf_fragment_too_long_fragment = field_ptr("fragment.too_long_fragment");
+        items.hf_fragment_error = field_ptr("fragment.error");
+        items.hf_fragment_count = field_ptr("fragment.count");
+        items.hf_reassembled_in = field_ptr("reassembled.in");
+        items.hf_reassembled_length = field_ptr("reassembled.length");
+        items.hf_reassembled_data = field_ptr("reassembled.data");
+        items.tag = plugin.alloc_string(&format!("{} fragments", definition.name));
+
+        // Fragments are matched to messages by their addresses and message ID
+        let table = plugin.alloc_value(std::mem::zeroed::<reassembly_table>());
+        reassembly_table_register(table, &addresses_reassembly_table_functions);
+
+        self.reassembly_tables.push(ReassemblyTable {
+            data_source_name: plugin.alloc_string(&format!("Reassembled {}", definition.name)),
+            table: table,
+            items: plugin.alloc_value(items),
+            id: definition.id,
+        });
+    }
+
+    // Get a reassembly table that has already been registered
+    fn get_reassembly_table(&self, id: &str) -> &ReassemblyTable {
+        self.reassembly_tables
+            .iter()
+            .find(|table| table.id == id)
+            .expect(format!("Reassembly table {} not registered in protocol.", id).as_str())
+    }
+
+    // Register all expert infos of the protocol
+    unsafe fn register_expert_infos(
+        &mut self,
+        plugin: RefMut<'_, WiresharkPlugin>,
+        definitions: Vec<WiresharkExpertDefinition>,
+    ) {
+        if definitions.is_empty() {
+            return;
+        }
+
+        let mut infos = Vec::with_capacity(definitions.len());
+        for definition in definitions.iter() {
+            let field = plugin.alloc_value(expert_field { ei: -1, hf: -1 });
+
+            let mut info: ei_register_info = std::mem::zeroed();
+            info.ids = field;
+            info.eiinfo.name = plugin.alloc_string(&definition.id);
+            info.eiinfo.group = definition.group.to_i32();
+            info.eiinfo.severity = definition.severity.to_i32();
+            info.eiinfo.summary = plugin.alloc_string(&definition.summary);
+            info.eiinfo.hf_info.hfinfo.id = -1;
+            info.eiinfo.hf_info.hfinfo.same_name_prev_id = -1;
+            infos.push(info);
+
+            self.experts.push(ExpertHandle {
+                id: definition.id.clone(),
+                field: field,
+            });
+        }
+
+        // The array must live as long as the plugin
+        let num_infos = infos.len() as c_int;
+        let infos_ptr = plugin.alloc_flat_c_array_iter(infos);
+
+        let module = expert_register_protocol(self.proto_handle);
+        expert_register_field_array(module, infos_ptr, num_infos);
+    }
+
+    // Get the expert field of an expert info that has already been registered
+    fn get_expert_field(&self, id: &str) -> *mut expert_field {
+        self.experts
+            .iter()
+            .find(|expert| expert.id == id)
+            .expect(format!("Expert info {} not registered in protocol.", id).as_str())
+            .field
+    }
+
+    // Register all dissector tables owned by the protocol
+    unsafe fn register_dissector_tables(
+        &mut self,
+        plugin: RefMut<'_, WiresharkPlugin>,
+        definitions: Vec<WiresharkDissectorTableDefinition>,
+    ) {
+        for definition in definitions {
+            // String tables take their case sensitivity instead of a display type
+            let param = match definition.key_type {
+                FieldType::String => STRING_CASE_SENSITIVE as c_int,
+                _ => definition.display.to_i32(),
+            };
+
+            let table = register_dissector_table(
+                plugin.alloc_string(&definition.id),
+                plugin.alloc_string(&definition.ui_name),
+                self.proto_handle,
+                definition.key_type.to_u32(),
+                param,
+            );
+
+            self.dissector_tables.push(DissectorTableHandle {
+                id: definition.id,
+                table: table,
+            });
+        }
+    }
+
+    // Get a dissector table that has already been registered
+    fn get_dissector_table(&self, id: &str) -> dissector_table_t {
+        self.dissector_tables
+            .iter()
+            .find(|table| table.id == id)
+            .expect(format!("Dissector table {} not registered in protocol.", id).as_str())
+            .table
+    }
+
+    // Look up all fields of other protocols read by the dissector, including the wanted fields of a postdissector.
+    // Must be called during handoff, once every protocol has registered its fields. Fields that no protocol
+    // registered, such as fields of a disabled or missing protocol, are reported and skipped, and have no values.
+    unsafe fn find_extracted_fields(&mut self) {
+        let mut names = self.extracted_field_names.take().unwrap();
+        if let Some(fields) = self.postdissector_fields.as_ref() {
+            names.extend(fields.iter().cloned());
+        }
+
+        for name in names {
+            match find_field_id(&name) {
+                Some(hf_id) => {
+                    self.extracted_fields.insert(name, hf_id);
+                }
+                None => {
+                    let message = CString::new(format!(
+                        "Protocol {} reads field {}, which is not registered by any protocol.",
+                        CStr::from_ptr(self.id).to_string_lossy(),
+                        name
+                    ))
+                    .unwrap();
+                    ws_log(null(), ws_log_level_LOG_LEVEL_WARNING, c"%s".as_ptr(), message.as_ptr());
+                }
+            }
+        }
+    }
+
+    // Get the ID of a field of another protocol. Fields that were not declared with add_extracted_field are
+    // looked up each time.
+    unsafe fn get_extracted_field_id(&self, abbrev: &str) -> Option<c_int> {
+        match self.extracted_fields.get(abbrev) {
+            Some(hf_id) => Some(*hf_id),
+            None => find_field_id(abbrev),
+        }
+    }
+
+    // Register the protocol as a postdissector if it is one, along with the fields it wants filled in. Must be
+    // called during handoff, once every protocol has registered its fields.
+    unsafe fn register_postdissector(&self) {
+        let fields = match self.postdissector_fields.as_ref() {
+            Some(fields) => fields,
+            None => return,
+        };
+
+        register_postdissector(self.dissector_handle);
+
+        // Wireshark takes ownership of the array of field IDs
+        let wanted_hfids = g_array_sized_new(0, 0, std::mem::size_of::<c_int>() as c_uint, fields.len() as c_uint);
+        for hf_id in fields.iter().filter_map(|field| self.extracted_fields.get(field)) {
+            g_array_append_vals(wanted_hfids, hf_id as *const c_int as *const c_void, 1);
+        }
+
+        set_postdissector_wanted_hfids(self.dissector_handle, wanted_hfids);
+    }
+
+    // Look up all dissectors this protocol calls on payloads. Must be called during handoff, once all
+    // dissectors have been registered.
+    unsafe fn find_called_dissectors(&mut self) {
+        for name in self.called_dissector_names.take().unwrap() {
+            let name_cstr = CString::new(name.as_str()).unwrap();
+            let handle = find_dissector_add_dependency(name_cstr.as_ptr(), self.proto_handle);
+
+            // The dissector may belong to a plugin that is not loaded, so calls to it are skipped instead
+            if handle.is_null() {
+                let message = CString::new(format!(
+                    "Protocol {} calls dissector {}, which is not registered with Wireshark.",
+                    CStr::from_ptr(self.id).to_string_lossy(),
+                    name
+                ))
+                .unwrap();
+                ws_log(null(), ws_log_level_LOG_LEVEL_WARNING, c"%s".as_ptr(), message.as_ptr());
+            }
+
+            self.called_dissectors.push(CalledDissectorHandle {
+                name: name,
+                handle: handle,
+            });
+        }
+    }
+
+    // Get the handle to a dissector that was declared as called by this protocol, which is null if Wireshark
+    // does not know the dissector
+    fn get_called_dissector(&self, name: &str) -> *mut dissector_handle {
+        self.called_dissectors
+            .iter()
+            .find(|called| called.name == name)
+            .expect(format!("Dissector {} not declared as called by protocol, use add_called_dissector.", name).as_str())
+            .handle
+    }
+
+    // Get the handle to the protocol's ETT
     fn get_ett_handle(&self, idx: c_int) -> c_int {
         if idx < 0 {
             panic!("ETT handle index must be >= 0");
@@ -427,10 +1526,52 @@ impl WiresharkProtocol {
     }
 
     // Get the handle to the protocol's ETT
-    fn get_proto_handle(&self) -> c_int {
+    pub(crate) fn get_proto_handle(&self) -> c_int {
         self.proto_handle
     }
 
+    // Get the filter template of the protocol's Follow Stream support
+    pub(crate) fn get_follow_filter_template(&self) -> &str {
+        self.follow_filter_template
+            .as_deref()
+            .expect("Protocol has no Follow Stream support, call set_follow_stream first.")
+    }
+
+    // Get the number of streams seen by Follow Stream
+    pub(crate) fn get_follow_stream_count(&self) -> u32 {
+        self.follow_stream_count.get()
+    }
+
+    // Forget the streams seen by Follow Stream, when a capture file is opened or dissected again
+    pub(crate) fn reset_follow_streams(&self) {
+        self.follow_stream_count.set(0);
+    }
+
+    // Get the stream currently being followed
+    pub(crate) fn get_followed_stream(&self) -> Option<u32> {
+        self.followed_stream.get()
+    }
+
+    // Set the stream that is about to be followed
+    pub(crate) fn set_followed_stream(&self, stream: u32) {
+        self.followed_stream.set(Some(stream));
+    }
+
+    // Get the handle of the protocol's tap
+    fn get_tap_id(&self) -> c_int {
+        self.tap_id
+    }
+
+    // Get the heuristic function registered for the given heuristic table
+    fn get_heuristic_fn(&self, table: &str) -> &HeuristicCallback {
+        &self
+            .heuristic_definitions
+            .iter()
+            .find(|def| def.table == table)
+            .expect(format!("Heuristic table {} not registered in protocol.", table).as_str())
+            .heuristic_fn
+    }
+
     // Get the handle to a field that has already been registered
     fn get_field_handle(&self, abbrev: &str) -> &FieldHandle {
         self.fields
@@ -449,10 +1590,18 @@ impl WiresharkProtocol {
         ett_index: c_int,
         length: c_int,
     ) -> DissectorSubTree<'a> {
+        // The dissector may have been called by another protocol with one of its subtrees, so the root of the
+        // packet tree is found by walking up from the given tree
+        let mut root = tree;
+        while !root.is_null() && !(*root).parent.is_null() {
+            root = (*root).parent;
+        }
+
         return DissectorSubTree::new(
             self,
             self.get_proto_handle(),
             pinfo,
+            root,
             tree,
             tvb,
             start_index,
@@ -466,7 +1615,7 @@ impl WiresharkProtocol {
     // pointer be used within the context of the function that is calling it and
     // NEVER stored anywher, as it will go invalid as soon as another function
     // calls this.
-    fn to_temp_cstring_fast(&self, s: &str) -> *const c_char {
+    pub(crate) fn to_temp_cstring_fast(&self, s: &str) -> *const c_char {
         let mut bufref = self.nullterm_buffer.borrow_mut();
         bufref.resize(s.len() + 1, 0);
         bufref[0..s.len()].copy_from_slice(s.as_bytes());
@@ -480,7 +1629,7 @@ pub struct TvBuff<'a> {
     _tvb: *mut tvbuff,
 
     // The offset into the tvbuff
-    _base_offset: u32,
+    base_offset: u32,
 
     //A resolved slice of the tvbuff
     cursor: Cursor<&'a [u8]>,
@@ -501,7 +1650,7 @@ impl<'a> TvBuff<'a> {
         let slice_length = (captured_length as i32).checked_sub(offset as i32).unwrap();
         Self {
             _tvb: tvb,
-            _base_offset: offset,
+            base_offset: offset,
             cursor: Cursor::new(Self::resolve_slice_offset(tvb, offset as i32, slice_length)),
             byteorder: target_byte_order,
         }
@@ -518,6 +1667,14 @@ impl<'a> TvBuff<'a> {
         std::slice::from_raw_parts(ptr, length as usize)
     }
 
+    // Change the byte order used for all subsequent reads
+    pub fn set_byteorder(&mut self, byteorder: TvBuffByteOrder) {
+        self.byteorder = match byteorder {
+            TvBuffByteOrder::BigEndian => ByteOrder::BigEndian,
+            TvBuffByteOrder::LittleEndian => ByteOrder::LittleEndian,
+        };
+    }
+
     // Read a sized object from the TvBuff
     pub fn read<T: StreamReader>(&mut self) -> std::io::Result<T> {
         T::read_from(&mut self.cursor, self.byteorder)
@@ -545,6 +1702,228 @@ impl<'a> TvBuff<'a> {
 
         return &slic[start..end];
     }
+
+    // Start reading individual bits from the current position of the cursor. The TvBuff itself is not advanced.
+    pub fn bit_reader(&self, bitorder: TvBuffBitOrder) -> TvBitReader<'a> {
+        TvBitReader {
+            data: *self.cursor.get_ref(),
+            base_offset: self.base_offset,
+            position: self.cursor.position() as usize * 8,
+            bitorder: bitorder,
+        }
+    }
+}
+
+// Describes the order in which the bits of each byte are read
+#[derive(Copy, Clone)]
+pub enum TvBuffBitOrder {
+    // Bits are read from the most significant bit of each byte first, and the first bit read is the most
+    // significant bit of the value. This is the order Wireshark uses for bit offsets in the tree.
+    MsbFirst,
+    // Bits are read from the least significant bit of each byte first, and the first bit read is the least
+    // significant bit of the value.
+    LsbFirst,
+}
+
+// Reads values of arbitrary bit widths from packet data
+pub struct TvBitReader<'a> {
+    // The packet data, starting at base_offset within the tvbuff
+    data: &'a [u8],
+
+    // The offset of the data into the tvbuff
+    base_offset: u32,
+
+    // The current bit position within the data
+    position: usize,
+
+    // Either MSB first or LSB first
+    bitorder: TvBuffBitOrder,
+}
+
+impl<'a> TvBitReader<'a> {
+    // Read an unsigned value of the given width, up to 64 bits
+    pub fn read_bits(&mut self, width: u32) -> std::io::Result<u64> {
+        if width > 64 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Cannot read more than 64 bits into a single value",
+            ));
+        }
+
+        if (width as usize) > self.remaining_bits() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Not enough bits left in the buffer",
+            ));
+        }
+
+        let mut value: u64 = 0;
+        for i in 0..width {
+            let byte = self.data[self.position / 8];
+            let bit_in_byte = self.position % 8;
+
+            match self.bitorder {
+                TvBuffBitOrder::MsbFirst => {
+                    let bit = (byte >> (7 - bit_in_byte)) & 1;
+                    value = (value << 1) | bit as u64;
+                }
+                TvBuffBitOrder::LsbFirst => {
+                    let bit = (byte >> bit_in_byte) & 1;
+                    value |= (bit as u64) << i;
+                }
+            }
+
+            self.position += 1;
+        }
+
+        Ok(value)
+    }
+
+    // Read a two's complement signed value of the given width, up to 64 bits
+    pub fn read_signed_bits(&mut self, width: u32) -> std::io::Result<i64> {
+        let value = self.read_bits(width)?;
+        if width == 0 {
+            return Ok(0);
+        }
+
+        // Sign extend from the top bit of the value
+        let shift = 64 - width;
+        Ok(((value << shift) as i64) >> shift)
+    }
+
+    // Read a single bit as a boolean
+    pub fn read_bool(&mut self) -> std::io::Result<bool> {
+        Ok(self.read_bits(1)? != 0)
+    }
+
+    // Skip over the given number of bits
+    pub fn skip_bits(&mut self, count: usize) -> std::io::Result<()> {
+        if count > self.remaining_bits() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Not enough bits left in the buffer",
+            ));
+        }
+
+        self.position += count;
+        Ok(())
+    }
+
+    // Skip to the start of the next byte, unless already at the start of a byte
+    pub fn align_to_byte(&mut self) {
+        self.position = (self.position + 7) / 8 * 8;
+        if self.position > self.data.len() * 8 {
+            self.position = self.data.len() * 8;
+        }
+    }
+
+    // Returns true if the reader is at the start of a byte
+    pub fn is_aligned(&self) -> bool {
+        self.position % 8 == 0
+    }
+
+    // The number of bits left to read
+    pub fn remaining_bits(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    // The current bit offset from the start of the tvbuff, which is the bit offset used to add bit fields to a
+    // DissectorSubTree. The offset counts bits in the reader's bit order, so with TvBuffBitOrder::LsbFirst it must
+    // be passed to add_bits_field with FieldEncoding::LittleEndian, which counts from the least significant bit too.
+    pub fn tvb_bit_offset(&self) -> u32 {
+        self.base_offset * 8 + self.position as u32
+    }
+
+    // The index of the byte within the tvbuff that holds the next bit to be read
+    pub fn tvb_byte_offset(&self) -> u32 {
+        self.base_offset + (self.position / 8) as u32
+    }
+}
+
+// A buffer of packet data created during dissection, such as a reassembled message or decoded data. Its
+// contents are valid until the dissection of the current packet ends.
+pub struct PacketBuffer {
+    // Pointer to the underlying tvbuff
+    tvb: *mut tvbuff,
+}
+
+impl PacketBuffer {
+    // Return the data of this buffer from its beginning
+    pub unsafe fn get_buffer<'a>(&self, byteorder: TvBuffByteOrder) -> TvBuff<'a> {
+        TvBuff::wrap(self.tvb, 0, byteorder)
+    }
+
+    // The captured length of this buffer
+    pub unsafe fn length(&self) -> u32 {
+        tvb_captured_length(self.tvb)
+    }
+}
+
+// Convert an address to an IP address, if it is an IPv4 or IPv6 address
+unsafe fn address_to_ip(address: &address) -> Option<IpAddr> {
+    if address.data.is_null() || address.len <= 0 {
+        return None;
+    }
+
+    let data = std::slice::from_raw_parts(address.data as *const u8, address.len as usize);
+    if address.type_ == address_type_AT_IPv4 as c_int && data.len() == 4 {
+        return Some(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])));
+    }
+
+    if address.type_ == address_type_AT_IPv6 as c_int && data.len() == 16 {
+        let octets: [u8; 16] = data.try_into().unwrap();
+        return Some(IpAddr::V6(Ipv6Addr::from(octets)));
+    }
+
+    None
+}
+
+// Holds typed values that are attached to Wireshark objects such as conversations. Each type can be
+// stored once, and is created with its default value when it is first requested.
+struct ProtoDataMap {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl ProtoDataMap {
+    // Allocate a new map which is dropped when Wireshark frees the capture file scope, which happens
+    // when the capture file is closed or reloaded
+    unsafe fn new_file_scoped() -> *mut ProtoDataMap {
+        let map = Box::into_raw(Box::new(ProtoDataMap {
+            values: HashMap::new(),
+        }));
+
+        wmem_register_callback(wmem_file_scope(), Some(drop_proto_data_map), map as *mut c_void);
+        map
+    }
+
+    // Get the value of the given type, inserting its default value if it does not exist yet
+    fn get_or_insert<T: Default + 'static>(&mut self) -> &mut T {
+        self.values
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()))
+            .downcast_mut::<T>()
+            .unwrap()
+    }
+}
+
+// Called by Wireshark when the scope a ProtoDataMap was allocated in is freed
+unsafe extern "C" fn drop_proto_data_map(
+    _allocator: *mut wmem_allocator_t,
+    _event: wmem_cb_event_t,
+    user_data: *mut c_void,
+) -> bool {
+    drop(Box::from_raw(user_data as *mut ProtoDataMap));
+
+    // Unregister the callback, the map is gone
+    false
+}
+
+// Describes the position of a fragment within its message
+pub enum FragmentPosition {
+    // The fragment is the nth fragment of the message, starting from 0
+    Sequence(u32),
+    // The fragment starts at the given byte offset within the message
+    Offset(u32),
 }
 
 // Describes the index of where the field should be linked to the packet data
@@ -567,8 +1946,12 @@ pub enum TvBuffByteOrder {
     LittleEndian,
 }
 
+// The subtree of a dissector, used to add items for the packet being dissected. The packet info and buffer are
+// valid for as long as the subtree exists, so reading the packet info is safe. Methods that call into Wireshark
+// to add items, change the columns or hand data to other dissectors are unsafe.
 pub struct DissectorSubTree<'a> {
     pinfo: *mut _packet_info,
+    root_node: *mut _proto_node,
     _parent_node: *mut _proto_node,
     top_item: *mut _proto_node,
     subtree_node: *mut _proto_node,
@@ -582,6 +1965,7 @@ impl<'a> DissectorSubTree<'a> {
     // Create a new tree node for a dissector function
     // proto: The protocol being used
     // hf_handle: The handle of the field that should be used for the top level item of before the subtree begins
+    // root: The root of the packet tree
     // parent: The parent node of the new node
     // tvb: The tvbuff being dissected
     // start_index: The start index for this slice
@@ -590,6 +1974,7 @@ impl<'a> DissectorSubTree<'a> {
         proto: &'a WiresharkProtocol,
         hf_handle: c_int,
         pinfo: *mut _packet_info,
+        root: *mut _proto_node,
         parent: *mut _proto_node,
         tvb: *mut tvbuff,
         start_index: c_int,
@@ -600,6 +1985,7 @@ impl<'a> DissectorSubTree<'a> {
         let self_node: *mut _proto_node = proto_item_add_subtree(new_item, proto.get_ett_handle(ett_index));
 
         let obj = Self {
+            root_node: root,
             _parent_node: parent,
             pinfo: pinfo,
             top_item: new_item,
@@ -627,6 +2013,11 @@ impl<'a> DissectorSubTree<'a> {
         self.proto.get_field_handle(field_id).handle
     }
 
+    // Get the current index within the packet, which is the index the next item will be placed at
+    pub fn get_index(&self) -> c_int {
+        self.cur_index
+    }
+
     // Return the data buffer at the beginning of this subtree
     pub unsafe fn get_buffer_start(&self, byteorder: TvBuffByteOrder) -> TvBuff {
         TvBuff::wrap(self.tvb, 0, byteorder)
@@ -707,6 +2098,104 @@ impl<'a> DissectorSubTree<'a> {
         self.add_item(field_id, index, length, encoding.to_u32())
     }
 
+    // Push an item to the tree that covers the given number of bits, starting at a bit offset from the start of the
+    // packet (see TvBitReader::tvb_bit_offset). Bits are counted from the most significant bit of each byte, or from
+    // the least significant bit with FieldEncoding::LittleEndian, which matches a TvBitReader using
+    // TvBuffBitOrder::LsbFirst. Does not increment the internal index tracker.
+    pub unsafe fn add_bits_field(
+        &mut self,
+        field_id: &str,
+        bit_offset: u32,
+        no_of_bits: c_int,
+        encoding: FieldEncoding,
+    ) -> DissectorItem {
+        let item = proto_tree_add_bits_item(
+            self.subtree_node,
+            self.proto.get_field_handle(field_id).handle,
+            self.tvb,
+            bit_offset,
+            no_of_bits,
+            encoding.to_u32(),
+        );
+
+        DissectorItem::new(self, self.tvb, item)
+    }
+
+    // Push an item to the tree that covers the given number of bits, like add_bits_field, and return the value of
+    // the bits along with the item. Does not increment the internal index tracker.
+    pub unsafe fn add_bits_field_ret_val(
+        &mut self,
+        field_id: &str,
+        bit_offset: u32,
+        no_of_bits: c_int,
+        encoding: FieldEncoding,
+    ) -> (u64, DissectorItem) {
+        let mut value: u64 = 0;
+        let item = proto_tree_add_bits_ret_val(
+            self.subtree_node,
+            self.proto.get_field_handle(field_id).handle,
+            self.tvb,
+            bit_offset,
+            no_of_bits,
+            &mut value,
+            encoding.to_u32(),
+        );
+
+        (value, DissectorItem::new(self, self.tvb, item))
+    }
+
+    // Move the current index, such as past the bytes covered by bit fields
+    pub fn set_index(&mut self, index: IndexPosition) {
+        self.cur_index = self.enum_to_index_position(index);
+    }
+
+    // Push a bitmask item to the tree, with a subtree that shows each of the given bit fields on its own line, and
+    // increment the current index by the size of the header field. The header field is an unsigned integer field
+    // covering the whole value, and each bit field is registered with WiresharkFieldArgs::with_bitmask.
+    pub unsafe fn add_bitmask(
+        &mut self,
+        header_field_id: &str,
+        index: IndexPosition,
+        ett_index: c_int,
+        bit_field_ids: &[&str],
+        encoding: FieldEncoding,
+    ) -> DissectorItem {
+        let header = self.proto.get_field_handle(header_field_id);
+
+        // Wireshark takes a null terminated array of pointers to the field handles
+        let mut bit_fields: Vec<*mut c_int> = bit_field_ids
+            .iter()
+            .map(|field_id| &self.proto.get_field_handle(field_id).handle as *const c_int as *mut c_int)
+            .collect();
+        bit_fields.push(null_mut());
+
+        let offset = self.enum_to_index_position(index);
+        let item = proto_tree_add_bitmask(
+            self.subtree_node,
+            self.tvb,
+            offset as c_uint,
+            header.handle,
+            self.proto.get_ett_handle(ett_index),
+            bit_fields.as_ptr(),
+            encoding.to_u32(),
+        );
+
+        // The bitmask covers the whole header field
+        self.cur_index += match header.field_type {
+            FieldType::Uint8 => 1,
+            FieldType::Uint16 => 2,
+            FieldType::Uint24 => 3,
+            FieldType::Uint32 => 4,
+            FieldType::Uint40 => 5,
+            FieldType::Uint48 => 6,
+            FieldType::Uint56 => 7,
+            FieldType::Uint64 => 8,
+            _ => panic!("Bitmask header field {} must be an unsigned integer field.", header_field_id),
+        };
+
+        DissectorItem::new(self, self.tvb, item)
+    }
+
     // Push a string item of the given length to the tree and increment the current index
     // by the length of the item.
     pub unsafe fn add_field_string(
@@ -787,6 +2276,7 @@ impl<'a> DissectorSubTree<'a> {
             self.proto,
             field_handle,
             self.pinfo,
+            self.root_node,
             self.subtree_node,
             self.tvb,
             self.enum_to_index_position(index),
@@ -805,6 +2295,7 @@ impl<'a> DissectorSubTree<'a> {
             self.proto,
             field_handle,
             self.pinfo,
+            self.root_node,
             self.subtree_node,
             self.tvb,
             self.enum_to_index_position(index),
@@ -817,21 +2308,439 @@ impl<'a> DissectorSubTree<'a> {
         subtree_tree
     }
 
-    // Set the info column to the given string
-    pub unsafe fn set_info_column(&mut self, info: &str) {
-        let cinfo = (*self.pinfo).cinfo;
-        let str = self.proto.to_temp_cstring_fast(info);
-        col_add_str(cinfo, COL_INFO as i32, str);
+    // Create a new subtree item over a buffer created during dissection, such as a reassembled message, and return
+    // the subtree management object. The subtree starts at the beginning of the buffer and items added to it refer
+    // to the bytes of that buffer. Does not increment the internal index tracker.
+    pub unsafe fn push_buffer_subtree(&mut self, buffer: &PacketBuffer, field_handle: c_int, ett_index: c_int) -> DissectorSubTree {
+        DissectorSubTree::new(
+            self.proto,
+            field_handle,
+            self.pinfo,
+            self.root_node,
+            self.subtree_node,
+            buffer.tvb,
+            0,
+            ett_index,
+            -1,
+        )
     }
 
-    // Get a reference to the top level item that the subtree is rooted off of
-    pub unsafe fn get_top_item(&mut self) -> DissectorItem {
-        DissectorItem::new(self, self.tvb, self.top_item)
+    // Create a buffer holding a copy of the given data, such as a payload after it was decompressed or decoded,
+    // and show it as a new tab with the given name in the packet bytes pane. Items added to a subtree over the
+    // buffer (see push_buffer_subtree) highlight the bytes of the decoded data.
+    pub unsafe fn add_child_buffer(&mut self, data: &[u8], name: &str) -> PacketBuffer {
+        // The data must live as long as the packet, so copy it into the packet scope
+        let pool = (*self.pinfo).pool;
+        let data_ptr = wmem_memdup(pool, data.as_ptr() as *const c_void, data.len()) as *const u8;
+
+        let child = tvb_new_child_real_data(self.tvb, data_ptr, data.len() as c_uint, data.len() as c_int);
+        add_new_data_source(self.pinfo, child, wmem_strdup(pool, self.proto.to_temp_cstring_fast(name)));
+
+        PacketBuffer { tvb: child }
     }
-}
 
-pub struct DissectorItem<'a> {
-    subtree: &'a DissectorSubTree<'a>,
+    // Add a fragment of the given length to the reassembly table and increment the current index by the length
+    // of the fragment. Fragments are grouped into messages by the message ID, and more_fragments is false for the
+    // last fragment of a message. The fragment list is added to the tree automatically, and the reassembled message
+    // is returned once it is complete. Frames holding earlier fragments show which frame the message was reassembled in.
+    // Returns None without adding the fragment if it is not within the captured data.
+    pub unsafe fn add_fragment(
+        &mut self,
+        table_id: &str,
+        index: IndexPosition,
+        length: c_int,
+        message_id: u32,
+        position: FragmentPosition,
+        more_fragments: bool,
+    ) -> Option<PacketBuffer> {
+        let table = self.proto.get_reassembly_table(table_id);
+        let offset = self.enum_to_index_position(index);
+
+        // Wireshark throws an exception for a fragment outside of the data, which must not unwind through Rust
+        let remaining = tvb_captured_length_remaining(self.tvb, offset);
+        if length < 0 || length > remaining {
+            return None;
+        }
+
+        let head = match position {
+            FragmentPosition::Sequence(number) => fragment_add_seq_check(
+                table.table,
+                self.tvb,
+                offset,
+                self.pinfo,
+                message_id,
+                null(),
+                number,
+                length as u32,
+                more_fragments,
+            ),
+            FragmentPosition::Offset(frag_offset) => fragment_add_check(
+                table.table,
+                self.tvb,
+                offset,
+                self.pinfo,
+                message_id,
+                null(),
+                frag_offset,
+                length as u32,
+                more_fragments,
+            ),
+        };
+
+        self.cur_index = offset + length;
+
+        // Build the reassembled buffer if the message is complete, or add the "Reassembled in" item if it was
+        // completed in another frame
+        let mut update_col_info = true;
+        let reassembled = process_reassembled_data(
+            self.tvb,
+            offset,
+            self.pinfo,
+            table.data_source_name,
+            head,
+            table.items,
+            &mut update_col_info,
+            self.subtree_node,
+        );
+
+        if reassembled.is_null() {
+            return None;
+        }
+
+        Some(PacketBuffer { tvb: reassembled })
+    }
+
+    // Get the state of the given type that is attached to the conversation this packet belongs to, creating the
+    // conversation and the state if they do not exist yet. The state lives until the capture file is closed or reloaded.
+    // Conversations are found by the addresses of the packet, so set any custom addresses before calling this.
+    pub unsafe fn conversation_data<T: Default + 'static>(&mut self) -> &mut T {
+        let conversation = find_or_create_conversation(self.pinfo);
+        let proto_handle = self.proto.get_proto_handle();
+
+        let mut map = conversation_get_proto_data(conversation, proto_handle) as *mut ProtoDataMap;
+        if map.is_null() {
+            map = ProtoDataMap::new_file_scoped();
+            conversation_add_proto_data(conversation, proto_handle, map as *mut c_void);
+        }
+
+        (*map).get_or_insert::<T>()
+    }
+
+    // Get the state of the given type that is attached to the current frame, creating it if it does not exist yet.
+    // Frames are dissected again whenever they are selected, so decisions made on the first pass should be stored
+    // here and read back on later passes. The state is shared by all PDUs of this protocol within the frame, and
+    // lives until the capture file is closed or reloaded.
+    pub unsafe fn packet_data<T: Default + 'static>(&mut self) -> &mut T {
+        let proto_handle = self.proto.get_proto_handle();
+
+        let mut map = p_get_proto_data(wmem_file_scope(), self.pinfo, proto_handle, 0) as *mut ProtoDataMap;
+        if map.is_null() {
+            map = ProtoDataMap::new_file_scoped();
+            p_add_proto_data(wmem_file_scope(), self.pinfo, proto_handle, 0, map as *mut c_void);
+        }
+
+        (*map).get_or_insert::<T>()
+    }
+
+    // Returns true if the current frame has already been dissected before, which is false during the first
+    // in-order pass over the capture file and true whenever the frame is dissected again
+    pub fn is_visited(&self) -> bool {
+        unsafe { (*(*self.pinfo).fd).visited() != 0 }
+    }
+
+    // The number of the frame being dissected, starting at 1
+    pub fn frame_number(&self) -> u32 {
+        unsafe { (*self.pinfo).num }
+    }
+
+    // The time the frame was captured, or None if the time is outside of the range of a system time
+    pub fn absolute_time(&self) -> Option<SystemTime> {
+        unsafe { nstime_to_system_time(&(*self.pinfo).abs_ts) }
+    }
+
+    // The time the frame was captured, relative to the first frame of the capture
+    pub fn relative_time(&self) -> Duration {
+        unsafe { nstime_to_duration(&(*self.pinfo).rel_ts) }
+    }
+
+    // The number of bytes of the frame that were captured
+    pub fn captured_length(&self) -> u32 {
+        unsafe { (*(*self.pinfo).fd).cap_len }
+    }
+
+    // The number of bytes of the frame as it was on the wire, which is more than the captured length if the
+    // frame was truncated
+    pub fn reported_length(&self) -> u32 {
+        unsafe { (*(*self.pinfo).fd).pkt_len }
+    }
+
+    // The source address of the packet, if the layers below set it to an IPv4 or IPv6 address
+    pub fn source_address(&self) -> Option<IpAddr> {
+        unsafe { address_to_ip(&(*self.pinfo).src) }
+    }
+
+    // The destination address of the packet, if the layers below set it to an IPv4 or IPv6 address
+    pub fn destination_address(&self) -> Option<IpAddr> {
+        unsafe { address_to_ip(&(*self.pinfo).dst) }
+    }
+
+    // The source port of the packet, set by the transport layer below
+    pub fn source_port(&self) -> u32 {
+        unsafe { (*self.pinfo).srcport }
+    }
+
+    // The destination port of the packet, set by the transport layer below
+    pub fn destination_port(&self) -> u32 {
+        unsafe { (*self.pinfo).destport }
+    }
+
+    // The type of transport layer the ports belong to
+    pub fn transport_type(&self) -> TransportType {
+        unsafe { TransportType::from_u32((*self.pinfo).ptype as u32).unwrap_or(TransportType::None) }
+    }
+
+    // The direction of the frame on a point-to-point link
+    pub fn peer_direction(&self) -> PeerDirection {
+        unsafe { PeerDirection::from_i32((*self.pinfo).p2p_dir).unwrap_or(PeerDirection::Unknown) }
+    }
+
+    // The link-layer encapsulation of the frame, one of the WTAP_ENCAP_ values, or None if the record is not a
+    // captured packet
+    pub fn encapsulation(&self) -> Option<i32> {
+        unsafe {
+            let rec = (*self.pinfo).rec;
+            if rec.is_null() || (*rec).rec_type != REC_TYPE_PACKET {
+                return None;
+            }
+
+            Some((*rec).rec_header.packet_header.pkt_encap)
+        }
+    }
+
+    // Get the values of every occurrence of a field of any protocol in the current packet tree, such as "ip.ttl",
+    // including fields of the layers below this protocol. Declare the field with add_extracted_field so it is only
+    // looked up once. Fields of other protocols are only present when the packet tree is being built, when they are
+    // used by a filter, or when they are wanted fields of a postdissector.
+    pub unsafe fn get_field_values(&self, abbrev: &str) -> Vec<FieldValue> {
+        match self.proto.get_extracted_field_id(abbrev) {
+            Some(hf_id) => read_field_values(self.root_node, hf_id),
+            None => Vec::new(),
+        }
+    }
+
+    // Get the value of the first occurrence of a field of any protocol in the current packet tree
+    pub unsafe fn get_field_value(&self, abbrev: &str) -> Option<FieldValue> {
+        self.get_field_values(abbrev).into_iter().next()
+    }
+
+    // Set the source address of the packet to an address of a type added with WiresharkPlugin::add_address_type.
+    // It is shown in the Source column and used to find the conversation of the packet.
+    pub unsafe fn set_source_address(&mut self, address_type: &str, data: &[u8]) {
+        let type_id = get_global_plugin().as_ref().borrow().get_address_type(address_type).type_id;
+        set_packet_address(self.pinfo, &mut (*self.pinfo).src, type_id, data);
+    }
+
+    // Set the destination address of the packet to an address of a type added with
+    // WiresharkPlugin::add_address_type. It is shown in the Destination column and used to find the conversation
+    // of the packet.
+    pub unsafe fn set_destination_address(&mut self, address_type: &str, data: &[u8]) {
+        let type_id = get_global_plugin().as_ref().borrow().get_address_type(address_type).type_id;
+        set_packet_address(self.pinfo, &mut (*self.pinfo).dst, type_id, data);
+    }
+
+    // Publish a file carried by the protocol once it is complete, such as the data of a reassembled message, so it
+    // can be saved with File -> Export Objects. The protocol must have called enable_export_objects. The data is only
+    // copied while objects are being exported.
+    pub unsafe fn export_object(&self, filename: &str, content_type: &str, data: &[u8]) {
+        if !have_tap_listener(self.proto.get_tap_id()) {
+            return;
+        }
+
+        self.queue_tap(ExportedObject {
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            payload: data.to_vec(),
+        });
+    }
+
+    // Publish the contents of a buffer, such as one returned by add_fragment once a message is reassembled, so it can
+    // be saved with File -> Export Objects
+    pub unsafe fn export_object_buffer(&self, filename: &str, content_type: &str, buffer: &PacketBuffer) {
+        let data = buffer.get_buffer(TvBuffByteOrder::BigEndian).into_slice(buffer.length() as i32);
+        self.export_object(filename, content_type, data);
+    }
+
+    // Emit payload bytes of a stream for Follow Stream, such as the application data of a message. The stream index
+    // is substituted into the protocol's filter template to select the packets of the stream, and the direction
+    // tells which side sent the payload. A frame can carry payloads of several streams, and each payload is only
+    // shown when its own stream is followed. Following a selected frame follows the first stream it carries. The
+    // protocol must have called set_follow_stream.
+    pub unsafe fn follow_stream_payload(&self, stream: u32, direction: FollowDirection, data: &[u8]) {
+        remember_follow_stream(self.pinfo, self.proto.get_proto_handle(), stream);
+
+        let count = &self.proto.follow_stream_count;
+        count.set(count.get().max(stream.saturating_add(1)));
+
+        if !have_tap_listener(self.proto.get_tap_id()) {
+            return;
+        }
+
+        self.queue_tap(FollowPayload {
+            stream: stream,
+            direction: direction,
+            payload: data.to_vec(),
+        });
+    }
+
+    // Queue a record to the protocol's tap, named after the protocol's filter, which is passed to every listener of
+    // the tap once the frame has been dissected. Nothing is queued if the tap has no listeners. The record lives until the frame is done.
+    pub unsafe fn queue_tap<T: 'static>(&self, record: T) {
+        queue_tap_record(self.proto.get_tap_id(), self.pinfo, record);
+    }
+
+    // Add an expert info item to the tree that is linked to the given range of packet data. Does not increment the
+    // internal index tracker.
+    pub unsafe fn add_expert(&mut self, expert_id: &str, index: IndexPosition, length: c_int) -> DissectorItem {
+        let item = proto_tree_add_expert(
+            self.subtree_node,
+            self.pinfo,
+            self.proto.get_expert_field(expert_id),
+            self.tvb,
+            self.enum_to_index_position(index),
+            length,
+        );
+
+        DissectorItem::new(self, self.tvb, item)
+    }
+
+    // Add an expert info item to the tree with the given text instead of the registered summary. Does not increment
+    // the internal index tracker.
+    pub unsafe fn add_expert_text(
+        &mut self,
+        expert_id: &str,
+        index: IndexPosition,
+        length: c_int,
+        text: &str,
+    ) -> DissectorItem {
+        let str = self.proto.to_temp_cstring_fast(text);
+        let item = proto_tree_add_expert_format(
+            self.subtree_node,
+            self.pinfo,
+            self.proto.get_expert_field(expert_id),
+            self.tvb,
+            self.enum_to_index_position(index),
+            length,
+            c"%s".as_ptr(),
+            str,
+        );
+
+        DissectorItem::new(self, self.tvb, item)
+    }
+
+    // Check if there are bytes left after the current index. Wireshark throws an exception when a subset starts past
+    // the end of the data, which must not unwind through Rust.
+    unsafe fn has_remaining_data(&self) -> bool {
+        tvb_reported_length_remaining(self.tvb, self.cur_index) > 0
+    }
+
+    // Hand the remaining bytes from the current index to the dissector registered for the given key in one of the
+    // protocol's uint dissector tables. Returns true if a dissector took the bytes, and increments the current index
+    // by the number of bytes it consumed. Returns false if there are no bytes left.
+    pub unsafe fn call_subdissector_uint(&mut self, table_id: &str, key: u32) -> bool {
+        if !self.has_remaining_data() {
+            return false;
+        }
+
+        let next_tvb = tvb_new_subset_remaining(self.tvb, self.cur_index);
+        let consumed = dissector_try_uint_new(
+            self.proto.get_dissector_table(table_id),
+            key,
+            next_tvb,
+            self.pinfo,
+            self.subtree_node,
+            true,
+            null_mut(),
+        );
+
+        self.cur_index += consumed.max(0);
+        consumed != 0
+    }
+
+    // Hand the remaining bytes from the current index to the dissector registered for the given key in one of the
+    // protocol's string dissector tables. Returns true if a dissector took the bytes, and increments the current index
+    // by the number of bytes it consumed. Returns false if there are no bytes left.
+    pub unsafe fn call_subdissector_string(&mut self, table_id: &str, key: &str) -> bool {
+        // Wireshark exposes the key to the subdissector while it runs, so it can't live in the temporary buffer
+        let key = CString::new(key).unwrap();
+
+        if !self.has_remaining_data() {
+            return false;
+        }
+
+        let next_tvb = tvb_new_subset_remaining(self.tvb, self.cur_index);
+        let consumed = dissector_try_string(
+            self.proto.get_dissector_table(table_id),
+            key.as_ptr(),
+            next_tvb,
+            self.pinfo,
+            self.subtree_node,
+            null_mut(),
+        );
+
+        self.cur_index += consumed.max(0);
+        consumed != 0
+    }
+
+    // Call a dissector registered with Wireshark on the given range of bytes, adding its items under this subtree.
+    // A length of -1 passes all remaining bytes. Returns the number of bytes the dissector consumed and increments
+    // the current index by that amount. The dissector must be declared with add_called_dissector. Returns 0 without
+    // calling the dissector if the range is not within the data, or if Wireshark does not know the dissector.
+    pub unsafe fn call_dissector(&mut self, name: &str, index: IndexPosition, length: c_int) -> c_int {
+        let handle = self.proto.get_called_dissector(name);
+        if handle.is_null() {
+            return 0;
+        }
+
+        let offset = self.enum_to_index_position(index);
+
+        // Wireshark throws an exception for a subset outside of the data, which must not unwind through Rust
+        let remaining = tvb_reported_length_remaining(self.tvb, offset);
+        if remaining <= 0 || length > remaining {
+            return 0;
+        }
+
+        let next_tvb = if length < 0 {
+            tvb_new_subset_remaining(self.tvb, offset)
+        } else {
+            tvb_new_subset_length(self.tvb, offset, length)
+        };
+
+        let consumed = call_dissector_with_data(handle, next_tvb, self.pinfo, self.subtree_node, null_mut());
+
+        self.cur_index = offset + consumed.max(0);
+        consumed
+    }
+
+    // Get the current values of the protocol's preferences
+    pub fn preferences(&self) -> Preferences<'a> {
+        Preferences::new(self.proto)
+    }
+
+    // Set the info column to the given string
+    pub unsafe fn set_info_column(&mut self, info: &str) {
+        let cinfo = (*self.pinfo).cinfo;
+        let str = self.proto.to_temp_cstring_fast(info);
+        col_add_str(cinfo, COL_INFO as i32, str);
+    }
+
+    // Get a reference to the top level item that the subtree is rooted off of
+    pub unsafe fn get_top_item(&mut self) -> DissectorItem {
+        DissectorItem::new(self, self.tvb, self.top_item)
+    }
+}
+
+pub struct DissectorItem<'a> {
+    subtree: &'a DissectorSubTree<'a>,
     tvb: *mut tvbuff,
     item: *mut _proto_node,
 }
@@ -879,6 +2788,27 @@ impl<'a> DissectorItem<'a> {
         proto_item_get_len(self.item)
     }
 
+    // Attach an expert info to this item
+    pub unsafe fn add_expert_info(&mut self, expert_id: &str) {
+        expert_add_info(
+            self.subtree.pinfo,
+            self.item,
+            self.subtree.proto.get_expert_field(expert_id),
+        );
+    }
+
+    // Attach an expert info to this item with the given text instead of the registered summary
+    pub unsafe fn add_expert_info_text(&mut self, expert_id: &str, text: &str) {
+        let str = self.subtree.proto.to_temp_cstring_fast(text);
+        expert_add_info_format(
+            self.subtree.pinfo,
+            self.item,
+            self.subtree.proto.get_expert_field(expert_id),
+            c"%s".as_ptr(),
+            str,
+        );
+    }
+
     // Mark the item as generated, and therefore consuming no packet data
     pub unsafe fn set_generated(&mut self) {
         if !self.item.is_null() {
@@ -907,6 +2837,9 @@ pub unsafe extern "C" fn proto_register_foo() {
 
     let plugref = get_global_plugin();
 
+    // Register the address types first, so they are available to all protocols
+    plugref.as_ref().borrow_mut().register_address_types();
+
     let definitions = plugref
         .as_ref()
         .borrow_mut()
@@ -919,23 +2852,66 @@ pub unsafe extern "C" fn proto_register_foo() {
         let mut proto = WiresharkProtocol::new(
             plugref.as_ref().borrow_mut(),
             def.match_definitions,
-            def.dissector_fn,
+            def.dissector,
             &def.name,
             &def.id,
             &def.filter,
             def.num_ett_fields
         );
 
+        // Keep the heuristics so they can be registered during handoff and looked up during dissection
+        proto.heuristic_definitions = def.heuristic_definitions;
+
+        // Keep the called dissectors so they can be looked up during handoff
+        proto.called_dissector_names = Some(def.called_dissectors);
+
+        // Keep the wanted fields of a postdissector so they can be looked up during handoff
+        proto.postdissector_fields = def.postdissector_fields;
+
+        // Keep the fields of other protocols the dissector reads, so they can be looked up during handoff
+        proto.extracted_field_names = Some(def.extracted_fields);
+
+        // Register all preferences
+        proto.register_preferences(plugref.as_ref().borrow_mut(), def.preferences, def.preferences_fn);
+
+        // Split TCP streams into PDUs if the protocol asked for it
+        if let Some(framing) = def.tcp_framing {
+            proto.register_tcp_framing(framing);
+        }
+
         // Register all field definitions
-        for field in def.fields.iter() {
-            proto.register_field(
-                plugref.as_ref().borrow_mut(),
-                &field.name,
-                &field.id,
-                field.field_type,
-                &field.str_values,
-                field.display,
-            );
+        for field in def.fields {
+            proto.register_field(plugref.as_ref().borrow_mut(), field);
+        }
+
+        // Register all dissector tables, so other protocols can match on them during handoff
+        proto.register_dissector_tables(plugref.as_ref().borrow_mut(), def.dissector_tables);
+
+        // Register all expert infos
+        proto.register_expert_infos(plugref.as_ref().borrow_mut(), def.expert_infos);
+
+        // Register all reassembly tables, which refer to the fields registered above
+        for table in def.reassembly_tables {
+            proto.register_reassembly_table(plugref.as_ref().borrow_mut(), table);
+        }
+
+        // Register the Conversations and Endpoints dialog support
+        if let Some(mut table) = def.conversation_table {
+            table.register(&plugref.as_ref().borrow(), proto.get_proto_handle());
+            proto.conversation_table = Some(table);
+        }
+
+        // Register the Export Objects support
+        if def.export_objects {
+            register_export_objects(proto.get_proto_handle());
+        }
+
+        // Register the Follow Stream support
+        if let Some(filter_template) = def.follow_filter_template {
+            let mut plugin = plugref.as_ref().borrow_mut();
+            let tap = plugin.alloc_string(&def.filter);
+            register_follow(&mut plugin, &def.id, proto.get_proto_handle(), tap);
+            proto.follow_filter_template = Some(filter_template);
         }
 
         // Keep the protocol in the plugin
@@ -951,6 +2927,7 @@ pub unsafe extern "C" fn proto_register_foo() {
 pub unsafe extern "C" fn proto_reg_handoff() {
     let plugref = get_global_plugin();
     let mut temp_stor: Vec<(*mut dissector_handle, Vec<WiresharkMatchDefinition>)> = Vec::new();
+    let mut heur_stor: Vec<(c_int, String, String, String, bool)> = Vec::new();
 
     // For each protocol, grab their match definitions and create a dissector for them
     for proto in plugref.as_ref().borrow_mut().protocols.values_mut() {
@@ -958,8 +2935,29 @@ pub unsafe extern "C" fn proto_reg_handoff() {
         let match_defs = proto.match_definitions.take().unwrap();
 
         let handle = create_dissector_handle(Some(dissection_dispatcher), proto.get_proto_handle());
+        proto.dissector_handle = handle;
+
+        // Look up the dissectors this protocol calls on payloads
+        proto.find_called_dissectors();
+
+        // Look up the fields of other protocols the dissector reads
+        proto.find_extracted_fields();
+
+        // Run the protocol after all other dissectors if it is a postdissector
+        proto.register_postdissector();
 
         temp_stor.push((handle, match_defs));
+
+        // Collect the heuristics for this protocol
+        for heur_def in proto.heuristic_definitions.iter() {
+            heur_stor.push((
+                proto.get_proto_handle(),
+                heur_def.table.clone(),
+                heur_def.display_name.clone(),
+                heur_def.internal_name.clone(),
+                heur_def.enabled,
+            ));
+        }
     }
 
     // Now apply all matchers to the dissectors
@@ -983,6 +2981,37 @@ pub unsafe extern "C" fn proto_reg_handoff() {
             }
         }
     }
+
+    // Now register all heuristics to their heuristic tables
+    for (proto_handle, table, display_name, internal_name, enabled) in heur_stor.iter() {
+        let plugin = plugref.as_ref().borrow();
+        let enable = if *enabled {
+            heuristic_enable_e_HEURISTIC_ENABLE
+        } else {
+            heuristic_enable_e_HEURISTIC_DISABLE
+        };
+
+        heur_dissector_add(
+            plugin.alloc_string(table),
+            Some(heuristic_dispatcher),
+            plugin.alloc_string(display_name),
+            plugin.alloc_string(internal_name),
+            *proto_handle,
+            enable,
+        );
+    }
+
+    // Now that every protocol has registered its tap, register all tap listeners
+    let tap_listeners = plugref.as_ref().borrow_mut().tap_listeners.take().unwrap();
+    for listener in tap_listeners {
+        listener.register();
+    }
+
+    // Register all statistics trees
+    let plugin = plugref.as_ref().borrow();
+    for tree in plugin.stats_trees.iter() {
+        tree.register(&plugin);
+    }
 }
 
 // The dissector function, called when a packet is dissected to any protocol registered in this framework.
@@ -1004,13 +3033,376 @@ pub unsafe extern "C" fn dissection_dispatcher(
     let name = CStr::from_ptr((*pinfo).current_proto);
     let proto = plugref.get_registered_protocol(name.to_str().unwrap());
 
-    // Clear the info column and set the protocol column to the name of the dissector
-    let cinfo = (*pinfo).cinfo;
-    col_set_str(cinfo, COL_PROTOCOL as i32, proto.id);
-    col_clear(cinfo, COL_INFO as i32);
+    dissect_protocol(proto, tvb, pinfo, tree)
+}
+
+// The heuristic function, called when a packet in a heuristic table is offered to any protocol registered in
+// this framework. If the protocol's heuristic function accepts the packet, it is dissected as normal.
+pub unsafe extern "C" fn heuristic_dispatcher(
+    tvb: *mut tvbuff,
+    pinfo: *mut _packet_info,
+    tree: *mut _proto_node,
+    _data: *mut c_void,
+) -> bool {
+    // Don't call the heuristic if we don't have a packet
+    if pinfo.is_null() {
+        return false;
+    }
+
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+
+    // Get the protocol that's being offered the packet and the table it's being offered from
+    let name = CStr::from_ptr((*pinfo).current_proto);
+    let proto = plugref.get_registered_protocol(name.to_str().unwrap());
+    let table = CStr::from_ptr((*pinfo).heur_list_name);
+
+    let heuristic_fn = proto.get_heuristic_fn(table.to_str().unwrap());
+    if !heuristic_fn(TvBuff::wrap(tvb, 0, TvBuffByteOrder::BigEndian)) {
+        return false;
+    }
+
+    dissect_protocol(proto, tvb, pinfo, tree) != 0
+}
+
+static PREFS_APPLY_DISPATCHERS: [Option<unsafe extern "C" fn()>; MAX_SLOTS] = slot_dispatchers!(prefs_apply_dispatcher);
+
+// Called by Wireshark after the preferences of the protocol with the preferences module in the given slot have
+// been applied
+unsafe extern "C" fn prefs_apply_dispatcher<const SLOT: usize>() {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+    let proto = plugref.get_registered_protocol(&plugref.preference_modules[SLOT]);
+
+    if let Some(preferences_fn) = proto.preferences_fn {
+        preferences_fn(Preferences::new(proto));
+    }
+}
+
+// Called by tcp_dissect_pdus to determine the length of the PDU starting at the given offset
+unsafe extern "C" fn tcp_pdu_length_dispatcher(
+    pinfo: *mut _packet_info,
+    tvb: *mut tvbuff,
+    offset: c_int,
+    _data: *mut c_void,
+) -> c_uint {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+
+    let name = CStr::from_ptr((*pinfo).current_proto);
+    let proto = plugref.get_registered_protocol(name.to_str().unwrap());
+    let framing = proto.tcp_framing.as_ref().unwrap();
+
+    (framing.pdu_len_fn)(TvBuff::wrap(tvb, offset as u32, TvBuffByteOrder::BigEndian))
+}
+
+// Called by tcp_dissect_pdus once for each complete PDU
+unsafe extern "C" fn tcp_pdu_dispatcher(
+    tvb: *mut tvbuff,
+    pinfo: *mut _packet_info,
+    tree: *mut _proto_node,
+    _data: *mut c_void,
+) -> c_int {
+    let plugin = get_global_plugin();
+    let plugref = plugin.as_ref().borrow();
+
+    let name = CStr::from_ptr((*pinfo).current_proto);
+    let proto = plugref.get_registered_protocol(name.to_str().unwrap());
+
+    run_dissector(proto, tvb, pinfo, tree)
+}
+
+// Dissect the packet with the given protocol, splitting it into PDUs first if the protocol runs over TCP
+unsafe fn dissect_protocol(
+    proto: &WiresharkProtocol,
+    tvb: *mut tvbuff,
+    pinfo: *mut _packet_info,
+    tree: *mut _proto_node,
+) -> c_int {
+    let snapshot = DissectionSnapshot::take(pinfo, tree);
+
+    // Clear the info column and set the protocol column to the name of the dissector. Postdissectors run on
+    // frames owned by other protocols, so they leave the columns alone.
+    if proto.postdissector_fields.is_none() {
+        let cinfo = (*pinfo).cinfo;
+        col_set_str(cinfo, COL_PROTOCOL as i32, proto.id);
+        col_clear(cinfo, COL_INFO as i32);
+    }
+
+    // Let the TCP dissector reassemble the stream and call us back once for each PDU. Packets that reach the
+    // protocol some other way, such as over UDP, are dissected as a whole.
+    let consumed = match proto.tcp_framing.as_ref() {
+        Some(framing) if (*pinfo).ptype as u32 == TransportType::Tcp.to_u32() => {
+            tcp_dissect_pdus(
+                tvb,
+                pinfo,
+                tree,
+                *framing.desegment,
+                framing.fixed_len,
+                Some(tcp_pdu_length_dispatcher),
+                Some(tcp_pdu_dispatcher),
+                null_mut(),
+            );
+
+            tvb_captured_length(tvb) as c_int
+        }
+        _ => run_dissector(proto, tvb, pinfo, tree),
+    };
+
+    // A rejected packet is left as the lower layer had it
+    if consumed == 0 {
+        snapshot.restore();
+    }
 
+    consumed
+}
+
+// Run the protocol's dissector function on the packet
+unsafe fn run_dissector(
+    proto: &WiresharkProtocol,
+    tvb: *mut tvbuff,
+    pinfo: *mut _packet_info,
+    tree: *mut _proto_node,
+) -> c_int {
+    let snapshot = DissectionSnapshot::take(pinfo, tree);
     let dissector = proto.start_dissector(tvb, pinfo, tree, 0, 0, -1);
-    (proto.dissector_fn)(dissector);
+    let top_item = dissector.top_item;
+
+    // Convert the result of the dissector into the return value Wireshark expects
+    match proto.dissector.dissect(dissector) {
+        DissectResult::Consumed(length) => {
+            // The dissector can't consume more than the data that was captured
+            let length = length.min(tvb_captured_length(tvb)) as c_int;
+            proto_item_set_len(top_item, length);
+            length
+        }
+        DissectResult::NotMine => {
+            snapshot.restore();
+            0
+        }
+        DissectResult::NeedMoreData(needed) => {
+            // Without desegmentation the rest of the data never arrives, so the packet is kept as it is
+            if (*pinfo).can_desegment == 0 {
+                return tvb_captured_length(tvb) as c_int;
+            }
+
+            // Ask the lower layer to call us again once the rest of the data has arrived
+            (*pinfo).desegment_offset = 0;
+            (*pinfo).desegment_len = if needed == 0 {
+                DESEGMENT_ONE_MORE_SEGMENT
+            } else {
+                needed
+            };
+
+            tvb_captured_length(tvb) as c_int
+        }
+    }
+}
+
+// The columns and tree of a packet before a dissector runs. If the dissector rejects the packet they are restored,
+// so it leaves no trace, like a C dissector that returns 0 before adding anything.
+struct DissectionSnapshot {
+    cinfo: *mut column_info,
+    tree: *mut _proto_node,
+    last_child: *mut _proto_node,
+    protocol_column: Option<CString>,
+    info_column: Option<CString>,
+}
+
+impl DissectionSnapshot {
+    unsafe fn take(pinfo: *mut _packet_info, tree: *mut _proto_node) -> Self {
+        let cinfo = (*pinfo).cinfo;
+        Self {
+            cinfo: cinfo,
+            tree: tree,
+            last_child: if tree.is_null() { null_mut() } else { (*tree).last_child },
+            protocol_column: Self::column_text(cinfo, COL_PROTOCOL as c_int),
+            info_column: Self::column_text(cinfo, COL_INFO as c_int),
+        }
+    }
+
+    unsafe fn restore(&self) {
+        self.restore_column(COL_PROTOCOL as c_int, &self.protocol_column);
+        self.restore_column(COL_INFO as c_int, &self.info_column);
+        self.restore_tree();
+    }
+
+    unsafe fn column_text(cinfo: *mut column_info, column: c_int) -> Option<CString> {
+        let text = col_get_text(cinfo, column);
+        if text.is_null() {
+            return None;
+        }
+
+        Some(CStr::from_ptr(text).to_owned())
+    }
+
+    // Clearing a column keeps the text before its fence, so only the text after it is added back
+    unsafe fn restore_column(&self, column: c_int, saved: &Option<CString>) {
+        let saved = match saved {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        col_clear(self.cinfo, column);
+        let kept = Self::column_text(self.cinfo, column).unwrap_or_default();
+        let rest = saved.as_bytes().strip_prefix(kept.as_bytes()).unwrap_or(saved.as_bytes());
+        let rest = CString::new(rest).unwrap();
+        col_append_str(self.cinfo, column, rest.as_ptr());
+    }
+
+    // Unlink the items added after the snapshot was taken. Their memory belongs to the packet scope.
+    unsafe fn restore_tree(&self) {
+        if self.tree.is_null() {
+            return;
+        }
+
+        let mut node = if self.last_child.is_null() {
+            (*self.tree).first_child
+        } else {
+            (*self.last_child).next
+        };
+        while !node.is_null() {
+            forget_tree_item(node);
+            node = (*node).next;
+        }
+
+        if self.last_child.is_null() {
+            (*self.tree).first_child = null_mut();
+        } else {
+            (*self.last_child).next = null_mut();
+        }
+        (*self.tree).last_child = self.last_child;
+    }
+}
+
+// Remove an item and its children from the fields Wireshark tracks for filters, so an unlinked item can't match
+unsafe fn forget_tree_item(node: *mut _proto_node) {
+    let finfo = (*node).finfo;
+    let tree_data = (*node).tree_data;
+    if !finfo.is_null() && !tree_data.is_null() && !(*tree_data).interesting_hfids.is_null() {
+        let hf_id = (*(*finfo).hfinfo).id as isize as *const c_void;
+        let finfos = g_hash_table_lookup((*tree_data).interesting_hfids, hf_id) as *mut GPtrArray;
+        if !finfos.is_null() {
+            g_ptr_array_remove(finfos, finfo as *mut c_void);
+        }
+    }
+
+    let mut child = (*node).first_child;
+    while !child.is_null() {
+        forget_tree_item(child);
+        child = (*child).next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bit_reader(data: &[u8], bitorder: TvBuffBitOrder) -> TvBitReader<'_> {
+        TvBitReader {
+            data: data,
+            base_offset: 0,
+            position: 0,
+            bitorder: bitorder,
+        }
+    }
+
+    #[test]
+    fn bit_reader_msb_first() {
+        let mut reader = bit_reader(&[0xb2, 0x5c], TvBuffBitOrder::MsbFirst);
+
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_bits(5).unwrap(), 0b10010);
+        assert!(reader.is_aligned());
+        assert_eq!(reader.read_bits(8).unwrap(), 0x5c);
+        assert_eq!(reader.remaining_bits(), 0);
+    }
+
+    #[test]
+    fn bit_reader_msb_first_across_bytes() {
+        let mut reader = bit_reader(&[0xb2, 0x5c], TvBuffBitOrder::MsbFirst);
+
+        assert_eq!(reader.read_bits(4).unwrap(), 0xb);
+        assert_eq!(reader.read_bits(8).unwrap(), 0x25);
+        assert_eq!(reader.read_bits(4).unwrap(), 0xc);
+    }
+
+    #[test]
+    fn bit_reader_lsb_first() {
+        let mut reader = bit_reader(&[0xb2, 0x5c], TvBuffBitOrder::LsbFirst);
+
+        assert_eq!(reader.read_bits(3).unwrap(), 0b010);
+        assert_eq!(reader.read_bits(5).unwrap(), 0b10110);
+        assert!(reader.is_aligned());
+        assert_eq!(reader.read_bits(8).unwrap(), 0x5c);
+        assert_eq!(reader.remaining_bits(), 0);
+    }
 
-    tvb_captured_length(tvb) as i32
+    #[test]
+    fn bit_reader_lsb_first_across_bytes() {
+        let mut reader = bit_reader(&[0xb2, 0x5c], TvBuffBitOrder::LsbFirst);
+
+        assert_eq!(reader.read_bits(4).unwrap(), 0x2);
+        assert_eq!(reader.read_bits(8).unwrap(), 0xcb);
+        assert_eq!(reader.read_bits(4).unwrap(), 0x5);
+    }
+
+    #[test]
+    fn bit_reader_64_bits() {
+        let data = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+
+        let mut reader = bit_reader(&data, TvBuffBitOrder::MsbFirst);
+        assert_eq!(reader.read_bits(64).unwrap(), 0x0123_4567_89ab_cdef);
+
+        let mut reader = bit_reader(&data, TvBuffBitOrder::LsbFirst);
+        assert_eq!(reader.read_bits(64).unwrap(), 0xefcd_ab89_6745_2301);
+
+        let mut reader = bit_reader(&[0xff; 9], TvBuffBitOrder::MsbFirst);
+        assert_eq!(reader.read_bits(65).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn bit_reader_sign_extension() {
+        let mut reader = bit_reader(&[0xf7, 0x80], TvBuffBitOrder::MsbFirst);
+        assert_eq!(reader.read_signed_bits(4).unwrap(), -1);
+        assert_eq!(reader.read_signed_bits(4).unwrap(), 7);
+        assert_eq!(reader.read_signed_bits(8).unwrap(), -128);
+
+        let mut reader = bit_reader(&[0xff; 8], TvBuffBitOrder::LsbFirst);
+        assert_eq!(reader.read_signed_bits(0).unwrap(), 0);
+        assert_eq!(reader.read_signed_bits(64).unwrap(), -1);
+    }
+
+    #[test]
+    fn bit_reader_past_end() {
+        let mut reader = bit_reader(&[0xff], TvBuffBitOrder::MsbFirst);
+
+        // A failed read leaves the position unchanged
+        assert_eq!(reader.read_bits(9).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(reader.skip_bits(9).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(reader.remaining_bits(), 8);
+
+        assert_eq!(reader.read_bits(8).unwrap(), 0xff);
+        assert!(reader.read_bool().is_err());
+
+        reader.align_to_byte();
+        assert_eq!(reader.remaining_bits(), 0);
+    }
+
+    #[test]
+    fn bit_reader_tvb_offsets() {
+        let mut reader = TvBitReader {
+            data: &[0x00, 0x00],
+            base_offset: 3,
+            position: 0,
+            bitorder: TvBuffBitOrder::MsbFirst,
+        };
+
+        reader.skip_bits(11).unwrap();
+        assert_eq!(reader.tvb_bit_offset(), 35);
+        assert_eq!(reader.tvb_byte_offset(), 4);
+
+        reader.align_to_byte();
+        assert_eq!(reader.tvb_bit_offset(), 40);
+    }
 }